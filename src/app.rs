@@ -1,7 +1,9 @@
 //! Core application code.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs;
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::str;
 
@@ -9,8 +11,11 @@ use regex_macro::regex;
 use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
-use crate::output::Output;
+use crate::external;
+use crate::output::{self, Output};
 use crate::prelude::*;
+use crate::preprocess::Directives;
+use crate::renderer::{self, Renderer};
 use crate::theme::Theme;
 use crate::util;
 
@@ -19,7 +24,7 @@ use crate::util;
 /////////////////////////////////////////////////////////////////////////
 
 /// Represents the TOML front matter of a Markdown document.
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct FrontMatter {
     /// The title for this page.
     title: Option<String>,
@@ -27,8 +32,25 @@ pub struct FrontMatter {
     description: Option<String>,
     /// The date this page was written.
     date: Option<chrono::NaiveDate>,
+    /// The date this page was last updated.
+    updated: Option<chrono::NaiveDate>,
     /// The type of page this is.
     kind: Option<String>,
+    /// Whether to include this page in the sitemap, defaults to true.
+    sitemap: Option<bool>,
+    /// Whether this page is a draft, defaults to false.
+    draft: Option<bool>,
+    /// The tags for this page.
+    tags: Option<Vec<String>>,
+    /// The categories this page belongs to.
+    categories: Option<Vec<String>>,
+    /// Whether to show a table of contents at the top of the page, defaults to
+    /// false.
+    toc: Option<bool>,
+    /// The template to render this page with.
+    template: Option<String>,
+    /// The last part of this page's URL, defaults to the file name.
+    slug: Option<String>,
     /// The rest of the TOML front matter.
     #[serde(flatten)]
     rest: toml::Value,
@@ -52,6 +74,12 @@ pub struct Page {
     pub front_matter: FrontMatter,
     /// The contents of the page.
     pub contents: String,
+    /// The destination of every link and image in the source file, along with
+    /// the line it is on. These are collected before preprocessing so that
+    /// the lines are not shifted by included files.
+    pub links: Vec<(String, usize)>,
+    /// The full paths of the files included into this page by directives.
+    pub includes: Vec<PathBuf>,
 }
 
 /// A builder to initialize a new project.
@@ -72,6 +100,28 @@ pub struct Project {
     theme: Theme,
     /// Each of the project's pages.
     pages: Vec<Page>,
+    /// Every other file in the src directory, these are copied to the output
+    /// directory as is.
+    assets: Vec<output::File>,
+}
+
+/// Rebuilds a project incrementally, only rendering pages affected by changes.
+#[derive(Debug)]
+pub struct Incremental {
+    /// The config used to locate the project, this is reloaded from disk on
+    /// every build.
+    config: Config,
+    /// The directives that can be used in pages.
+    directives: Directives,
+    /// The included files for each page in the last build, keyed by the full
+    /// path to the page's source file.
+    includes: HashMap<PathBuf, Vec<PathBuf>>,
+    /// The URL path of each page in the last build, keyed by the path to the
+    /// page's source file relative to the src directory.
+    urls: HashMap<PathBuf, String>,
+    /// Every file in the output directory after the last build, relative to
+    /// the output directory.
+    outputs: HashSet<PathBuf>,
 }
 
 /////////////////////////////////////////////////////////////////////////
@@ -84,12 +134,64 @@ impl Default for FrontMatter {
             title: None,
             description: None,
             date: None,
+            updated: None,
             kind: None,
+            sitemap: None,
+            draft: None,
+            tags: None,
+            categories: None,
+            toc: None,
+            template: None,
+            slug: None,
             rest: toml::Value::default(),
         }
     }
 }
 
+impl FrontMatter {
+    /// The type of page this is.
+    pub fn kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    /// The template to render this page with.
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
+
+    /// The date this page was written.
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        self.date
+    }
+
+    /// The last part of this page's URL.
+    pub fn slug(&self) -> Option<&str> {
+        self.slug.as_deref()
+    }
+
+    /// Returns this page's terms for the given taxonomy.
+    ///
+    /// Tags and categories have their own fields, any other taxonomy is looked
+    /// up in the rest of the front matter and can be a string or an array of
+    /// strings.
+    pub fn terms(&self, taxonomy: &str) -> Vec<&str> {
+        let terms = match taxonomy {
+            "tags" => &self.tags,
+            "categories" => &self.categories,
+            name => {
+                return match self.rest.get(name) {
+                    Some(toml::Value::String(term)) => vec![term.as_str()],
+                    Some(toml::Value::Array(terms)) => {
+                        terms.iter().filter_map(toml::Value::as_str).collect()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+        };
+        terms.iter().flatten().map(String::as_str).collect()
+    }
+}
+
 impl fmt::Display for FrontMatter {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "+++\n{}+++\n", toml::to_string_pretty(self).unwrap())
@@ -126,14 +228,55 @@ impl str::FromStr for RawPage {
 impl Page {
     /// Load a `Page` from the given path.
     pub fn from_path(src_dir: &Path, full_path: &Path) -> Result<Self> {
-        let raw_page = RawPage::from_path(&full_path)?;
-        let path = full_path.strip_prefix(&src_dir).unwrap().to_path_buf();
+        let text = fs::read_to_string(full_path).context("failed to read file")?;
+        let raw_page: RawPage = text.parse().context("failed to parse file contents")?;
+        // The lines before the contents are taken up by the front matter.
+        let line_offset = text[..text.len() - raw_page.contents.len()]
+            .matches('\n')
+            .count();
+        let links = Renderer::new(&raw_page.contents)
+            .links()
+            .into_iter()
+            .map(|(url, line)| (url, line + line_offset))
+            .collect();
+        let path = full_path.strip_prefix(src_dir).unwrap().to_path_buf();
         Ok(Self {
             path,
             front_matter: raw_page.front_matter,
             contents: raw_page.contents,
+            links,
+            includes: Vec::new(),
         })
     }
+
+    /// Whether this page is a draft.
+    pub fn is_draft(&self) -> bool {
+        self.front_matter.draft.unwrap_or(false)
+    }
+
+    /// Returns the pages in the given set that this page links to.
+    ///
+    /// Links can either be to the Markdown or rendered HTML file and are
+    /// resolved relative to this page.
+    fn links_to<'a>(&self, pages: &'a HashSet<PathBuf>) -> Vec<&'a PathBuf> {
+        Renderer::new(&self.contents)
+            .links()
+            .into_iter()
+            .map(|(url, _)| url)
+            .filter(|url| !url.contains(':') && !url.starts_with('/'))
+            .filter_map(|url| {
+                let url = url.split(&['#', '?'][..]).next().unwrap();
+                if url.is_empty() {
+                    return None;
+                }
+                let mut path = util::normalize_path(&self.path.parent().unwrap().join(url));
+                if path.extension().map(|s| s == "html").unwrap_or(false) {
+                    path.set_extension("md");
+                }
+                pages.get(&path)
+            })
+            .collect()
+    }
 }
 
 impl Builder {
@@ -169,6 +312,15 @@ impl Builder {
         self
     }
 
+    /// Update the path to write the config file to.
+    pub fn config_path<P>(&mut self, path: P) -> &mut Self
+    where
+        P: Into<PathBuf>,
+    {
+        *self.config.path_mut() = path.into();
+        self
+    }
+
     /// Whether to create a `.gitignore` file.
     pub fn gitignore(&mut self, gitignore: bool) -> &mut Self {
         self.gitignore = gitignore;
@@ -206,8 +358,23 @@ fn main() {
             .with_context(|| format!("failed to create src directory `{}`", src_dir.display()))?;
 
         if self.gitignore {
-            // Create .gitignore file.
-            util::write_new(self.config.root_dir().join(".gitignore"), "public\n")?;
+            // Create .gitignore file, ignoring the output directory if it is
+            // inside the project.
+            let contents = match self
+                .config
+                .output_dir()
+                .strip_prefix(self.config.root_dir())
+            {
+                Ok(path) => {
+                    let components: Vec<_> = path.components().map(|c| c.as_os_str()).collect();
+                    format!(
+                        "/{}\n",
+                        Join::join(components.as_slice(), "/").to_string_lossy()
+                    )
+                }
+                Err(_) => String::new(),
+            };
+            util::write_new(self.config.root_dir().join(".gitignore"), contents)?;
         }
 
         // Create config file.
@@ -224,59 +391,257 @@ fn main() {
 }
 
 impl Project {
-    /// Load a `Project` from the given directory.
-    pub fn from_path<P>(root_dir: P) -> Result<Self>
-    where
-        P: Into<PathBuf>,
-    {
-        let config = Config::from_path(root_dir.into()).context("failed to load config")?;
+    /// Load a `Project` using the root directory and config file path of the
+    /// given `Config`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let config = config.load().context("failed to load config")?;
         let theme = Theme::from_path(&config.theme_dir()).context("failed to load theme")?;
 
-        // Finally load all the the pages from disk.
+        // Finally load all the the pages and assets from disk.
         let src_dir = config.src_dir();
-        let pages: Vec<_> = walkdir::WalkDir::new(&src_dir)
+        let output_dir = config.output_dir();
+        let mut pages = Vec::new();
+        let mut assets = Vec::new();
+        for entry in walkdir::WalkDir::new(&src_dir)
             .into_iter()
+            .filter_entry(|e| e.path() != output_dir)
             .filter_map(Result::ok)
-            .filter(|e| e.path().extension().map(|s| s == "md").unwrap_or(false))
-            .map(|e| {
-                Page::from_path(&src_dir, e.path())
-                    .with_context(|| format!("failed to load page `{}`", e.path().display()))
-            })
-            .collect::<Result<_, _>>()?;
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path();
+            if path.extension().map(|s| s == "md").unwrap_or(false) {
+                pages.push(
+                    Page::from_path(&src_dir, path)
+                        .with_context(|| format!("failed to load page `{}`", path.display()))?,
+                );
+            } else {
+                let relative_path = path.strip_prefix(&src_dir).unwrap().to_path_buf();
+                assets.push(output::File::copy(relative_path, path.to_path_buf()));
+            }
+        }
+
+        // Warn about any published pages linking to drafts, since these links
+        // will be broken once the project is published.
+        let drafts: HashSet<_> = pages
+            .iter()
+            .filter(|page| page.is_draft())
+            .map(|page| page.path.clone())
+            .collect();
+        for page in pages.iter().filter(|page| !page.is_draft()) {
+            for draft in page.links_to(&drafts) {
+                log::warn!(
+                    "page `{}` links to draft page `{}`",
+                    page.path.display(),
+                    draft.display()
+                );
+            }
+        }
+        if !config.drafts() {
+            pages.retain(|page| !page.is_draft());
+        }
 
         Ok(Self {
             config,
             theme,
             pages,
+            assets,
         })
     }
 
-    /// Preprocess a `Project`.
-    pub fn preprocess(self) -> Result<Self> {
+    /// Preprocess a `Project`, expanding the given directives and then running
+    /// any external preprocessors.
+    pub fn preprocess(self, directives: &Directives) -> Result<Self> {
         let Self {
             config,
             theme,
             pages,
+            assets,
         } = self;
 
         let pages = pages
             .into_iter()
-            .map(|page| page.preprocess(&config))
+            .map(|page| page.preprocess(&config, directives))
             .collect::<Result<_, _>>()?;
+        let pages = external::preprocess(&config, pages)?;
 
         Ok(Self {
             config,
             theme,
             pages,
+            assets,
         })
     }
 
     /// Render a `Project`.
     pub fn render(self) -> Result<Output> {
-        Ok(self
+        self.render_filtered(|_| true)
+    }
+
+    /// Render a `Project`, only outputting the pages that match the given
+    /// predicate.
+    fn render_filtered<F>(self, filter: F) -> Result<Output>
+    where
+        F: Fn(&Page) -> bool,
+    {
+        let mut output = self
             .theme
-            .render(self.config, self.pages)
-            .context("failed to render project")?)
+            .render_filtered(self.config, self.pages, filter)
+            .context("failed to render project")?;
+        for asset in self.assets {
+            output.push_file(asset);
+        }
+        Ok(output)
+    }
+}
+
+/// Whether a link to any page resolves differently with the new URLs.
+///
+/// A link to a page that does not exist falls back to a URL that mirrors its
+/// path, so adding or removing a page only matters if its URL differs from
+/// that.
+fn urls_changed(
+    old: &HashMap<PathBuf, String>,
+    new: &HashMap<PathBuf, String>,
+    pretty_urls: bool,
+) -> bool {
+    old.keys().chain(new.keys()).any(|path| {
+        let url = |urls: &HashMap<PathBuf, String>| {
+            urls.get(path)
+                .cloned()
+                .unwrap_or_else(|| renderer::fallback_url(path, pretty_urls))
+        };
+        url(old) != url(new)
+    })
+}
+
+impl Incremental {
+    /// Create a new `Incremental` for the project with the given `Config`,
+    /// expanding the given directives in pages.
+    pub fn new(config: Config, directives: Directives) -> Self {
+        Self {
+            config,
+            directives,
+            includes: HashMap::new(),
+            urls: HashMap::new(),
+            outputs: HashSet::new(),
+        }
+    }
+
+    /// Get a reference to the `Config` used to locate the project.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the full paths of all files included by pages in the last build.
+    pub fn includes(&self) -> impl Iterator<Item = &PathBuf> {
+        self.includes.values().flatten()
+    }
+
+    /// Completely build the project and write it to disk.
+    pub fn build(&mut self) -> Result<Output> {
+        self.run(None)
+    }
+
+    /// Rebuild the project after the given files changed.
+    ///
+    /// Only pages whose source or included files changed are rendered with
+    /// their template and written to disk, unless the config or theme changed
+    /// or the URL of any page changed, in which case the whole project is
+    /// rebuilt so that links to other pages stay up to date. The Markdown of every page
+    /// is still rendered, because listings and feeds include the content of
+    /// every page. Files from the last build that are no longer part of the
+    /// output, for example because a page was removed or its URL changed, are
+    /// removed.
+    pub fn rebuild(&mut self, changed: &[PathBuf]) -> Result<Output> {
+        self.run(Some(changed))
+    }
+
+    fn run(&mut self, changed: Option<&[PathBuf]>) -> Result<Output> {
+        let project = Project::from_config(&self.config)
+            .context("failed to load project")?
+            .preprocess(&self.directives)
+            .context("failed to preprocess project")?;
+
+        let config = &project.config;
+        let src_dir = config.src_dir();
+        let output_dir = config.output_dir();
+
+        // Pages that are not affected by the changes are not written again, so
+        // their output from the last build is kept.
+        let mut outputs = HashSet::new();
+        let mut includes = HashMap::with_capacity(project.pages.len());
+        let mut urls = HashMap::with_capacity(project.pages.len());
+        for page in &project.pages {
+            outputs.insert(page.output_path(config).with_context(|| {
+                format!(
+                    "failed to get output path for page `{}`",
+                    page.path.display()
+                )
+            })?);
+            includes.insert(src_dir.join(&page.path), page.includes.clone());
+            urls.insert(page.path.clone(), page.url_path(config)?);
+        }
+        let previous = mem::replace(&mut self.includes, includes);
+
+        // The changed paths come from the watcher which canonicalizes them, so
+        // paths are canonicalized in the same way before comparing them.
+        let config_path = util::canonicalize(config.path().to_path_buf());
+        let theme_dir = util::canonicalize(config.theme_dir());
+        let changed = changed.filter(|changed| {
+            !urls_changed(&self.urls, &urls, config.pretty_urls())
+                && !changed
+                    .iter()
+                    .any(|path| *path == config_path || path.starts_with(&theme_dir))
+        });
+
+        let output = match changed {
+            Some(changed) => {
+                let is_changed =
+                    |path: &Path| changed.contains(&util::canonicalize(path.to_path_buf()));
+                let is_affected = |page: &Page| {
+                    let path = src_dir.join(&page.path);
+                    is_changed(&path)
+                        || page
+                            .includes
+                            .iter()
+                            .chain(previous.get(&path).into_iter().flatten())
+                            .any(|include| is_changed(include))
+                };
+                let output = project.render_filtered(is_affected)?;
+                output
+                    .update_path()
+                    .context("failed to write rendered project")?;
+                output
+            }
+            None => {
+                let output = project.render().context("failed to render project")?;
+                output
+                    .to_path()
+                    .context("failed to write rendered project")?;
+                output
+            }
+        };
+        outputs.extend(output.files().iter().map(|file| file.path().to_path_buf()));
+
+        // Remove any files from the last build that are no longer part of the
+        // output, along with any directories that are left empty.
+        for path in self.outputs.difference(&outputs) {
+            let path = output_dir.join(path);
+            util::remove_file(&path)?;
+            for dir in path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != output_dir)
+            {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+        self.urls = urls;
+        self.outputs = outputs;
+
+        Ok(output)
     }
 }
 
@@ -288,8 +653,6 @@ impl Project {
 mod tests {
     use super::*;
 
-    use std::panic;
-
     use toml::toml;
 
     #[test]
@@ -371,11 +734,11 @@ testing...
                     title: Some("Hello World!".to_string()),
                     description: Some("My first post!".to_string()),
                     date: Some(chrono::NaiveDate::from_ymd(2020, 3, 21)),
-                    kind: None,
                     rest: toml! {
                         testing_int = 5
                         testing_str = "hello"
-                    }
+                    },
+                    ..Default::default()
                 }
             }
         );
@@ -387,13 +750,14 @@ testing...
         let root_dir = temp_dir.path().to_path_buf();
         fs::create_dir(root_dir.join("src")).unwrap();
         fs::write(root_dir.join("belong.toml"), "").unwrap();
-        let project = Project::from_path(root_dir.clone()).unwrap();
+        let project = Project::from_config(&Config::new(root_dir.clone())).unwrap();
         assert_eq!(
             project,
             Project {
                 config: Config::new(root_dir.clone()),
                 theme: Theme::from_path(&root_dir.join("theme")).unwrap(),
                 pages: Vec::new(),
+                assets: Vec::new(),
             }
         )
     }
@@ -402,7 +766,7 @@ testing...
     fn project_from_path_missing_config() {
         let temp_dir = tempfile::tempdir().unwrap();
         let root_dir = temp_dir.path().to_path_buf();
-        let err = Project::from_path(root_dir.clone()).unwrap_err();
+        let err = Project::from_config(&Config::new(root_dir.clone())).unwrap_err();
         assert_eq!(
             format!("{:?}", err),
             format!(
@@ -427,7 +791,7 @@ Caused by:
         let temp_dir = tempfile::tempdir().unwrap();
         let root_dir = temp_dir.path().to_path_buf();
         fs::write(root_dir.join("belong.toml"), "very bad toml").unwrap();
-        let err = Project::from_path(root_dir.clone()).unwrap_err();
+        let err = Project::from_config(&Config::new(root_dir.clone())).unwrap_err();
         assert_eq!(
             format!("{:?}", err),
             format!(
@@ -455,8 +819,8 @@ bad toml
 testing...
 "#;
         let page_path = root_dir.join("src").join("test.md");
-        fs::write(&page_path, &page_content).unwrap();
-        let err = Project::from_path(root_dir.clone()).unwrap_err();
+        fs::write(&page_path, page_content).unwrap();
+        let err = Project::from_config(&Config::new(root_dir.clone())).unwrap_err();
         assert_eq!(
             format!("{:?}", err),
             format!(
@@ -494,15 +858,239 @@ date = "2020-03-2"
 testing...
 "#;
         let page_path = root_dir.join("src").join("test.md");
-        fs::write(&page_path, &page_content).unwrap();
-        let project = Project::from_path(root_dir.clone()).unwrap();
+        fs::write(&page_path, page_content).unwrap();
+        fs::create_dir(src_dir.join("images")).unwrap();
+        fs::write(src_dir.join("images").join("test.png"), b"\x89PNG\xff").unwrap();
+        let project = Project::from_config(&Config::new(root_dir.clone())).unwrap();
         assert_eq!(
             project,
             Project {
-                config: Config::from_path(root_dir.clone()).unwrap(),
+                config: Config::new(root_dir.clone()).load().unwrap(),
                 theme: Theme::from_path(&root_dir.join("theme")).unwrap(),
                 pages: vec![Page::from_path(&src_dir, &page_path).unwrap()],
+                assets: vec![output::File::copy(
+                    ["images", "test.png"].iter().collect(),
+                    src_dir.join("images").join("test.png")
+                )],
             }
         )
     }
+
+    #[test]
+    fn incremental_rebuild_only_affected_pages() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = fs::canonicalize(temp_dir.path()).unwrap();
+        let src_dir = root_dir.join("src");
+        let output_dir = root_dir.join("output");
+        fs::create_dir(&src_dir).unwrap();
+        fs::write(root_dir.join("belong.toml"), "").unwrap();
+        fs::write(root_dir.join("listing.rs"), "fn main() {}").unwrap();
+        fs::write(src_dir.join("a.md"), "{{#include ../listing.rs}}").unwrap();
+        fs::write(src_dir.join("b.md"), "testing...").unwrap();
+        fs::write(src_dir.join("c.md"), "testing...").unwrap();
+
+        let mut build = Incremental::new(Config::new(root_dir.clone()), Directives::default());
+        build.build().unwrap();
+        assert_eq!(
+            build.includes().collect::<Vec<_>>(),
+            vec![&root_dir.join("listing.rs")]
+        );
+
+        // Remove the rendered pages so that we can tell which were rewritten.
+        fs::remove_file(output_dir.join("a.html")).unwrap();
+        fs::remove_file(output_dir.join("b.html")).unwrap();
+        fs::remove_file(src_dir.join("c.md")).unwrap();
+        fs::write(root_dir.join("listing.rs"), "fn main() { }").unwrap();
+        build
+            .rebuild(&[root_dir.join("listing.rs"), src_dir.join("c.md")])
+            .unwrap();
+
+        assert!(output_dir.join("a.html").exists());
+        assert!(!output_dir.join("b.html").exists());
+        assert!(!output_dir.join("c.html").exists());
+        assert!(output_dir.join("index.html").exists());
+    }
+
+    #[test]
+    fn incremental_rebuild_removes_stale_outputs() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = fs::canonicalize(temp_dir.path()).unwrap();
+        let src_dir = root_dir.join("src");
+        let output_dir = root_dir.join("output");
+        fs::create_dir(&src_dir).unwrap();
+        fs::write(
+            root_dir.join("belong.toml"),
+            "[build]\npretty_urls = true\npaginate_by = 1",
+        )
+        .unwrap();
+        let post = |slug: &str, tags: &str| {
+            format!(
+                "+++\ntitle = \"A\"\nkind = \"post\"\ndate = \"2020-01-01\"\nslug = \"{}\"\n\
+                 tags = [{}]\n+++\n",
+                slug, tags
+            )
+        };
+        fs::write(src_dir.join("a.md"), post("a", "\"rust\"")).unwrap();
+        fs::write(src_dir.join("b.md"), post("b", "")).unwrap();
+
+        let mut build = Incremental::new(Config::new(root_dir.clone()), Directives::default());
+        build.build().unwrap();
+        assert!(output_dir.join("a").join("index.html").exists());
+        assert!(output_dir
+            .join("page")
+            .join("2")
+            .join("index.html")
+            .exists());
+        assert!(output_dir
+            .join("tags")
+            .join("rust")
+            .join("index.html")
+            .exists());
+
+        fs::write(src_dir.join("a.md"), post("renamed", "")).unwrap();
+        fs::remove_file(src_dir.join("b.md")).unwrap();
+        build
+            .rebuild(&[src_dir.join("a.md"), src_dir.join("b.md")])
+            .unwrap();
+
+        assert!(output_dir.join("renamed").join("index.html").exists());
+        assert!(output_dir.join("index.html").exists());
+        assert!(!output_dir.join("a").exists());
+        assert!(!output_dir.join("b").exists());
+        assert!(!output_dir.join("page").exists());
+        assert!(!output_dir.join("tags").exists());
+    }
+
+    #[test]
+    fn incremental_rebuild_updates_links_to_moved_pages() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = fs::canonicalize(temp_dir.path()).unwrap();
+        let src_dir = root_dir.join("src");
+        let output_dir = root_dir.join("output");
+        fs::create_dir(&src_dir).unwrap();
+        fs::write(root_dir.join("belong.toml"), "").unwrap();
+        fs::write(src_dir.join("a.md"), "[b](b.md)").unwrap();
+        fs::write(src_dir.join("b.md"), "+++\nslug = \"old\"\n+++\n").unwrap();
+
+        let mut build = Incremental::new(Config::new(root_dir.clone()), Directives::default());
+        build.build().unwrap();
+        assert!(fs::read_to_string(output_dir.join("a.html"))
+            .unwrap()
+            .contains("href=\"old.html\""));
+
+        fs::write(src_dir.join("b.md"), "+++\nslug = \"new\"\n+++\n").unwrap();
+        build.rebuild(&[src_dir.join("b.md")]).unwrap();
+
+        assert!(!output_dir.join("old.html").exists());
+        assert!(output_dir.join("new.html").exists());
+        assert!(fs::read_to_string(output_dir.join("a.html"))
+            .unwrap()
+            .contains("href=\"new.html\""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn incremental_rebuild_symlinked_theme() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = fs::canonicalize(temp_dir.path()).unwrap();
+        let src_dir = root_dir.join("src");
+        let templates_dir = root_dir.join("shared").join("templates");
+        fs::create_dir(&src_dir).unwrap();
+        fs::create_dir_all(&templates_dir).unwrap();
+        std::os::unix::fs::symlink(root_dir.join("shared"), root_dir.join("theme")).unwrap();
+        fs::write(root_dir.join("belong.toml"), "").unwrap();
+        fs::write(src_dir.join("a.md"), "testing...").unwrap();
+        fs::write(templates_dir.join("page.html"), "old").unwrap();
+
+        let mut build = Incremental::new(Config::new(root_dir.clone()), Directives::default());
+        build.build().unwrap();
+
+        fs::write(templates_dir.join("page.html"), "new").unwrap();
+        build.rebuild(&[templates_dir.join("page.html")]).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root_dir.join("output").join("a.html")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn builder_init_gitignore() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        Builder::new(root_dir.clone())
+            .title("My Blog")
+            .author("Me")
+            .init()
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(root_dir.join(".gitignore")).unwrap(),
+            "/output\n"
+        );
+        assert!(root_dir.join("src").join("hello-world.md").exists());
+    }
+
+    #[test]
+    fn page_links_to() {
+        let pages: HashSet<PathBuf> = vec![
+            PathBuf::from("draft.md"),
+            ["posts", "draft.md"].iter().collect(),
+        ]
+        .into_iter()
+        .collect();
+        let page = Page {
+            path: ["posts", "page.md"].iter().collect(),
+            contents: r#"
+[a](draft.md) [b](../draft.html#heading) [c](https://example.com/draft.md)
+[d](other.md) ![e](draft.md)
+"#
+            .to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            page.links_to(&pages),
+            vec![
+                &["posts", "draft.md"].iter().collect::<PathBuf>(),
+                &PathBuf::from("draft.md"),
+                &["posts", "draft.md"].iter().collect::<PathBuf>(),
+            ]
+        );
+    }
+
+    #[test]
+    fn project_from_config_drafts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        let src_dir = root_dir.join("src");
+        fs::create_dir(&src_dir).unwrap();
+        fs::write(root_dir.join("belong.toml"), "").unwrap();
+        fs::write(src_dir.join("draft.md"), "+++\ndraft = true\n+++\n").unwrap();
+        fs::write(src_dir.join("page.md"), "[draft](draft.md)").unwrap();
+
+        let mut config = Config::new(root_dir);
+        let project = Project::from_config(&config).unwrap();
+        assert_eq!(project.pages.len(), 1);
+        assert_eq!(project.pages[0].path, PathBuf::from("page.md"));
+
+        *config.drafts_mut() = true;
+        let project = Project::from_config(&config).unwrap();
+        assert_eq!(project.pages.len(), 2);
+    }
+
+    #[test]
+    fn front_matter_terms() {
+        let contents = r#"
++++
+tags = ["rust", "web"]
+series = "Learning Rust"
+authors = ["Alice", "Bob"]
++++
+"#;
+        let raw_page: RawPage = contents.parse().unwrap();
+        let front_matter = raw_page.front_matter;
+        assert_eq!(front_matter.terms("tags"), vec!["rust", "web"]);
+        assert_eq!(front_matter.terms("categories"), Vec::<&str>::new());
+        assert_eq!(front_matter.terms("series"), vec!["Learning Rust"]);
+        assert_eq!(front_matter.terms("authors"), vec!["Alice", "Bob"]);
+    }
 }
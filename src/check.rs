@@ -0,0 +1,394 @@
+//! Check that the links in a rendered project resolve.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use regex_macro::regex;
+
+use crate::output::{File, Output};
+use crate::prelude::*;
+use crate::util;
+
+/////////////////////////////////////////////////////////////////////////
+// Check definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// Why a link is broken.
+#[derive(Debug, PartialEq)]
+pub enum Reason {
+    /// The link points to a file that is not in the output.
+    MissingFile,
+    /// The link's fragment does not match any id in the target file.
+    MissingAnchor(String),
+}
+
+/// A link inside the project that does not resolve.
+#[derive(Debug, PartialEq)]
+pub struct BrokenLink {
+    /// The file containing the link, relative to the project's root directory.
+    ///
+    /// Links in a page's content are reported in the page's source file, any
+    /// other links, for example those in the theme's templates, are reported
+    /// in the rendered file.
+    pub path: PathBuf,
+    /// The line the link is on.
+    pub line: usize,
+    /// The destination of the link, as it is written in the file.
+    pub url: String,
+    /// Why the link is broken.
+    pub reason: Reason,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Check implementations
+/////////////////////////////////////////////////////////////////////////
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFile => write!(f, "no such file"),
+            Self::MissingAnchor(id) => write!(f, "no heading with id `{}`", id),
+        }
+    }
+}
+
+impl fmt::Display for BrokenLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: broken link `{}`, {}",
+            self.path.display(),
+            self.line,
+            self.url,
+            self.reason
+        )
+    }
+}
+
+/// Decode the HTML character references that are used when escaping
+/// attributes.
+fn unescape_html(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&#x2F;", "/")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Decode the percent-encoded characters in a URL, leaving it as is if it
+/// can not be decoded.
+fn decode(url: &str) -> String {
+    util::percent_decode(url).unwrap_or_else(|| url.to_string())
+}
+
+/// Returns the destination of every link and image in the HTML, along with
+/// the line it is on.
+fn html_links(html: &str) -> Vec<(String, usize)> {
+    let re =
+        regex!(r#"<(?:a\s(?:[^>]*?\s)?href|img\s(?:[^>]*?\s)?src)\s*=\s*(?:"([^"]*)"|'([^']*)')"#);
+    re.captures_iter(html)
+        .map(|captures| {
+            let m = captures.get(1).or_else(|| captures.get(2)).unwrap();
+            let line = html[..m.start()].matches('\n').count() + 1;
+            (unescape_html(m.as_str()), line)
+        })
+        .collect()
+}
+
+/// Returns every element id in the HTML.
+fn html_ids(html: &str) -> HashSet<String> {
+    let re = regex!(r#"\sid\s*=\s*(?:"([^"]*)"|'([^']*)')"#);
+    re.captures_iter(html)
+        .map(|captures| {
+            let m = captures.get(1).or_else(|| captures.get(2)).unwrap();
+            unescape_html(m.as_str())
+        })
+        .collect()
+}
+
+/// Split a link into whether it is relative to the root of the project, its
+/// path, and its fragment.
+///
+/// Returns `None` if the link points outside the project.
+fn split_link<'a>(
+    url: &'a str,
+    base_url: Option<&str>,
+) -> Option<(bool, &'a str, Option<&'a str>)> {
+    let (absolute, url) = match base_url.and_then(|base_url| url.strip_prefix(base_url)) {
+        Some(url) => (true, url),
+        None if url.starts_with("//") || regex!(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").is_match(url) => {
+            return None
+        }
+        None => match url.strip_prefix('/') {
+            Some(url) => (true, url),
+            None => (false, url),
+        },
+    };
+    let (url, fragment) = match url.find('#') {
+        Some(i) => (&url[..i], Some(&url[i + 1..])),
+        None => (url, None),
+    };
+    let path = url.split('?').next().unwrap();
+    Some((absolute, path, fragment))
+}
+
+/// Find the output file that a link in the given file points to.
+fn target_path(
+    files: &HashMap<&Path, &File>,
+    from: &Path,
+    absolute: bool,
+    path: &str,
+) -> Option<PathBuf> {
+    if path.is_empty() && !absolute {
+        return Some(from.to_path_buf());
+    }
+    let path = decode(path);
+    let target = if absolute {
+        util::normalize_path(Path::new(&path))
+    } else {
+        util::normalize_path(&from.parent().unwrap().join(&path))
+    };
+    if target
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    let index = target.join("index.html");
+    let candidates = if path.is_empty() || path.ends_with('/') {
+        vec![index]
+    } else {
+        vec![target, index]
+    };
+    candidates
+        .into_iter()
+        .find(|candidate| files.contains_key(candidate.as_path()))
+}
+
+/// Check every link and image in the rendered HTML files of the `Output`.
+pub fn check_links(output: &Output) -> Vec<BrokenLink> {
+    let config = output.config();
+    let base_url = config.base_url();
+    let relative = |path: PathBuf| match path.strip_prefix(config.root_dir()) {
+        Ok(path) => path.to_path_buf(),
+        Err(_) => path,
+    };
+
+    let files: HashMap<&Path, &File> = output.files().iter().map(|f| (f.path(), f)).collect();
+    let mut ids: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+    let mut broken = Vec::new();
+
+    for file in output.files() {
+        let html = match file.text() {
+            Some(text) if file.path().extension().is_some_and(|ext| ext == "html") => text,
+            _ => continue,
+        };
+        let mut source_links: Vec<_> = file
+            .source()
+            .map(|source| source.links.iter().map(Some).collect())
+            .unwrap_or_default();
+
+        for (url, line) in html_links(html) {
+            // Find where the link came from, each link in the source is only
+            // matched once so that repeated links get the right line.
+            let decoded = decode(&url);
+            let location = source_links
+                .iter_mut()
+                .find(|link| link.is_some_and(|link| decode(&link.resolved) == decoded))
+                .and_then(Option::take)
+                .map(|link| {
+                    let source = file.source().unwrap();
+                    let path = relative(config.src_dir().join(&source.path));
+                    (path, link.line, link.url.clone())
+                })
+                .unwrap_or_else(|| {
+                    let path = relative(config.output_dir().join(file.path()));
+                    (path, line, url.clone())
+                });
+
+            let (absolute, path, fragment) = match split_link(&url, base_url.as_deref()) {
+                Some(link) => link,
+                None => continue,
+            };
+            let reason = match target_path(&files, file.path(), absolute, path) {
+                None => Some(Reason::MissingFile),
+                Some(target) => match (fragment, files[target.as_path()].text()) {
+                    (Some(fragment), Some(text)) if !fragment.is_empty() => {
+                        let fragment = decode(fragment);
+                        let ids = ids.entry(target).or_insert_with(|| html_ids(text));
+                        if ids.contains(&fragment) {
+                            None
+                        } else {
+                            Some(Reason::MissingAnchor(fragment))
+                        }
+                    }
+                    _ => None,
+                },
+            };
+            if let Some(reason) = reason {
+                let (path, line, url) = location;
+                broken.push(BrokenLink {
+                    path,
+                    line,
+                    url,
+                    reason,
+                });
+            }
+        }
+    }
+    broken
+}
+
+/// Check every link in the `Output`, logging each broken link.
+///
+/// Returns an error if any links are broken.
+pub fn check(output: &Output) -> Result<()> {
+    let broken = check_links(output);
+    for link in &broken {
+        log::error!("{}", link);
+    }
+    match broken.len() {
+        0 => {
+            log::info!("all links are valid");
+            Ok(())
+        }
+        1 => Err(anyhow!("found 1 broken link")),
+        n => Err(anyhow!("found {} broken links", n)),
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::app::Project;
+    use crate::config::Config;
+    use crate::output::{Link, Source};
+    use crate::preprocess::Directives;
+
+    #[test]
+    fn html_links_basic() {
+        let html = "<a href=\"a.html\">\n<img alt=\"b\" src='b.png'>\n<a class=\"x\" \
+                    href=\"c.html?x=1&amp;y=2\">\n<link href=\"d.css\">";
+        assert_eq!(
+            html_links(html),
+            vec![
+                ("a.html".to_string(), 1),
+                ("b.png".to_string(), 2),
+                ("c.html?x=1&y=2".to_string(), 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_link_basic() {
+        let base_url = Some("https://example.com/");
+        assert_eq!(
+            split_link("a.html#x", None),
+            Some((false, "a.html", Some("x")))
+        );
+        assert_eq!(split_link("/a/?q#", None), Some((true, "a/", Some(""))));
+        assert_eq!(
+            split_link("https://example.com/a.html", base_url),
+            Some((true, "a.html", None))
+        );
+        assert_eq!(split_link("https://example.org/a.html", base_url), None);
+        assert_eq!(split_link("mailto:me@example.com", None), None);
+        assert_eq!(split_link("//example.com", None), None);
+    }
+
+    #[test]
+    fn check_links_source_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        let src_dir = root_dir.join("src");
+        fs::create_dir(&src_dir).unwrap();
+        fs::write(root_dir.join("belong.toml"), "").unwrap();
+        fs::write(root_dir.join("five.md"), "1\n2\n3\n4\n5\n").unwrap();
+        fs::write(
+            src_dir.join("a.md"),
+            "+++\ntitle = \"A\"\n+++\n{{#include ../five.md}}\n\n[broken](nope.md)\n",
+        )
+        .unwrap();
+        let output = Project::from_config(&Config::new(root_dir))
+            .unwrap()
+            .preprocess(&Directives::default())
+            .unwrap()
+            .render()
+            .unwrap();
+        assert_eq!(
+            check_links(&output),
+            vec![BrokenLink {
+                path: ["src", "a.md"].iter().collect(),
+                line: 6,
+                url: "nope.md".to_string(),
+                reason: Reason::MissingFile,
+            }]
+        );
+    }
+
+    #[test]
+    fn check_links_broken() {
+        let mut output = Output::new(Config::new(PathBuf::from("root")));
+        output
+            .push_file(File::new(
+                "index.html".into(),
+                "<a href=\"posts/a/\">\n<a href=\"missing.html\">\n<a href=\"posts/a/#intro\">",
+            ))
+            .push_file(
+                File::new(
+                    ["posts", "a", "index.html"].iter().collect(),
+                    "<h1 id=\"intro\">Intro</h1>\n<p><a href=\"../b.html#nope\">b</a>\n\
+                     <img src=\"../../image.png\"></p>\n<a href=\"../../\">",
+                )
+                .with_source(Source {
+                    path: ["posts", "a.md"].iter().collect(),
+                    links: vec![
+                        Link {
+                            url: "b.md#nope".to_string(),
+                            resolved: "../b.html#nope".to_string(),
+                            line: 3,
+                        },
+                        Link {
+                            url: "../image.png".to_string(),
+                            resolved: "../../image.png".to_string(),
+                            line: 5,
+                        },
+                    ],
+                }),
+            )
+            .push_file(File::new(["posts", "b.html"].iter().collect(), "<p></p>"));
+        assert_eq!(
+            check_links(&output),
+            vec![
+                BrokenLink {
+                    path: ["output", "index.html"].iter().collect(),
+                    line: 2,
+                    url: "missing.html".to_string(),
+                    reason: Reason::MissingFile,
+                },
+                BrokenLink {
+                    path: ["src", "posts", "a.md"].iter().collect(),
+                    line: 3,
+                    url: "b.md#nope".to_string(),
+                    reason: Reason::MissingAnchor("nope".to_string()),
+                },
+                BrokenLink {
+                    path: ["src", "posts", "a.md"].iter().collect(),
+                    line: 5,
+                    url: "../image.png".to_string(),
+                    reason: Reason::MissingFile,
+                },
+            ]
+        );
+    }
+}
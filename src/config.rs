@@ -1,11 +1,13 @@
 //! Configuration for a `Project`.
 
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::str;
 
 use serde::{Deserialize, Serialize};
 
 use crate::prelude::*;
+use crate::util;
 
 /////////////////////////////////////////////////////////////////////////
 // Config definitions
@@ -18,6 +20,43 @@ struct ProjectConfig {
     title: Option<String>,
     /// The project's authors.
     authors: Option<Vec<String>>,
+    /// The URL the project will be hosted at.
+    base_url: Option<String>,
+}
+
+/// Build specific configuration.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+struct BuildConfig {
+    /// The directory containing the Markdown pages.
+    src_dir: Option<PathBuf>,
+    /// The directory containing the theme.
+    theme_dir: Option<PathBuf>,
+    /// The directory to write the rendered project to.
+    output_dir: Option<PathBuf>,
+    /// The number of pages to list on each page of a listing.
+    paginate_by: Option<usize>,
+    /// The theme to use for syntax highlighting.
+    highlight_theme: Option<String>,
+    /// Whether to render each page to its own directory and link to it
+    /// without the `index.html`.
+    pretty_urls: Option<bool>,
+}
+
+/// A taxonomy used to group pages.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct TaxonomyConfig {
+    /// The name of the taxonomy, this is also the front matter field.
+    name: String,
+}
+
+/// An external command that preprocesses pages.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct PreprocessorConfig {
+    /// The command to run, defaults to `belong-<name>`.
+    command: Option<String>,
+    /// The rest of the preprocessor's configuration.
+    #[serde(flatten)]
+    rest: toml::Value,
 }
 
 /// The raw config file.
@@ -26,6 +65,18 @@ struct RawConfig {
     /// Project specific configuration.
     #[serde(default)]
     project: ProjectConfig,
+    /// Build specific configuration.
+    #[serde(default, skip_serializing_if = "BuildConfig::is_empty")]
+    build: BuildConfig,
+    /// The taxonomies used to group pages.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taxonomies: Option<Vec<TaxonomyConfig>>,
+    /// Permalink patterns for each kind of page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permalinks: Option<BTreeMap<String, String>>,
+    /// External preprocessors, keyed by name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preprocessor: Option<BTreeMap<String, PreprocessorConfig>>,
     /// The rest of the TOML configuration file.
     #[serde(flatten)]
     rest: toml::Value,
@@ -39,6 +90,10 @@ struct RawConfig {
 pub struct Config {
     /// The project's root directory.
     root_dir: PathBuf,
+    /// The path to the config file.
+    path: PathBuf,
+    /// Whether to include draft pages.
+    drafts: bool,
     /// The configuration as represented on disk.
     inner: RawConfig,
 }
@@ -47,10 +102,21 @@ pub struct Config {
 // Config implementations
 /////////////////////////////////////////////////////////////////////////
 
+impl BuildConfig {
+    /// Whether none of the build configuration is set.
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
 impl Default for RawConfig {
     fn default() -> Self {
         Self {
             project: ProjectConfig::default(),
+            build: BuildConfig::default(),
+            taxonomies: None,
+            permalinks: None,
+            preprocessor: None,
             rest: toml::Value::default(),
         }
     }
@@ -67,18 +133,26 @@ impl str::FromStr for RawConfig {
 impl Config {
     /// Create a new default `Config`.
     pub fn new(root_dir: PathBuf) -> Self {
+        let path = root_dir.join("belong.toml");
         Self {
             root_dir,
+            path,
+            drafts: false,
             inner: RawConfig::default(),
         }
     }
 
-    /// Load a `Config` from disk.
-    pub fn from_path(root_dir: PathBuf) -> Result<Self> {
-        let path = root_dir.join("belong.toml");
-        let inner = RawConfig::from_path(&path)
-            .with_context(|| format!("failed to load config file `{}`", path.display()))?;
-        Ok(Self { root_dir, inner })
+    /// Load the config file from disk, returning a new `Config` that was
+    /// instantiated the same way as this one.
+    pub fn load(&self) -> Result<Self> {
+        let inner = RawConfig::from_path(&self.path)
+            .with_context(|| format!("failed to load config file `{}`", self.path.display()))?;
+        Ok(Self {
+            root_dir: self.root_dir.clone(),
+            path: self.path.clone(),
+            drafts: self.drafts,
+            inner,
+        })
     }
 
     /// Get the root directory.
@@ -87,8 +161,23 @@ impl Config {
     }
 
     /// The path to config file.
-    pub fn path(&self) -> PathBuf {
-        self.root_dir.join("belong.toml")
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get a mutable reference to the path to the config file.
+    pub fn path_mut(&mut self) -> &mut PathBuf {
+        &mut self.path
+    }
+
+    /// Whether to include draft pages.
+    pub fn drafts(&self) -> bool {
+        self.drafts
+    }
+
+    /// Get a mutable reference to whether to include draft pages.
+    pub fn drafts_mut(&mut self) -> &mut bool {
+        &mut self.drafts
     }
 
     /// Convert a `Config` to raw TOML bytes.
@@ -102,19 +191,93 @@ impl Config {
         &self.inner
     }
 
+    /// Resolve a configured directory relative to the root directory.
+    fn resolve_dir(&self, dir: &Option<PathBuf>, default: &str) -> PathBuf {
+        let dir = dir.as_deref().unwrap_or_else(|| Path::new(default));
+        util::normalize_path(&self.root_dir.join(dir))
+    }
+
     /// Get the src directory.
     pub fn src_dir(&self) -> PathBuf {
-        self.root_dir.join("src")
+        self.resolve_dir(&self.inner.build.src_dir, "src")
     }
 
     /// Get the theme directory.
     pub fn theme_dir(&self) -> PathBuf {
-        self.root_dir.join("theme")
+        self.resolve_dir(&self.inner.build.theme_dir, "theme")
     }
 
     /// Get the output directory.
     pub fn output_dir(&self) -> PathBuf {
-        self.root_dir.join("output")
+        self.resolve_dir(&self.inner.build.output_dir, "output")
+    }
+
+    /// Get the URL the project will be hosted at, always ending with a slash.
+    pub fn base_url(&self) -> Option<String> {
+        self.inner.project.base_url.as_ref().map(|url| {
+            if url.ends_with('/') {
+                url.clone()
+            } else {
+                format!("{}/", url)
+            }
+        })
+    }
+
+    /// Get the number of pages to list on each page of a listing, if listings
+    /// should be paginated.
+    pub fn paginate_by(&self) -> Option<usize> {
+        self.inner.build.paginate_by
+    }
+
+    /// Whether to render each page to its own directory and link to it without
+    /// the `index.html`, defaults to false.
+    pub fn pretty_urls(&self) -> bool {
+        self.inner.build.pretty_urls.unwrap_or(false)
+    }
+
+    /// Get the theme to use for syntax highlighting.
+    pub fn highlight_theme(&self) -> &str {
+        self.inner
+            .build
+            .highlight_theme
+            .as_deref()
+            .unwrap_or("base16-ocean.dark")
+    }
+
+    /// Get the names of the taxonomies used to group pages.
+    ///
+    /// Defaults to tags and categories.
+    pub fn taxonomies(&self) -> Vec<&str> {
+        match &self.inner.taxonomies {
+            Some(taxonomies) => taxonomies.iter().map(|t| t.name.as_str()).collect(),
+            None => vec!["tags", "categories"],
+        }
+    }
+
+    /// Get the permalink pattern for the given kind of page, if there is one.
+    pub fn permalink(&self, kind: &str) -> Option<&str> {
+        self.inner
+            .permalinks
+            .as_ref()
+            .and_then(|permalinks| permalinks.get(kind))
+            .map(String::as_str)
+    }
+
+    /// Get the name and command of each external preprocessor, in order of
+    /// name.
+    pub fn preprocessors(&self) -> Vec<(&str, String)> {
+        self.inner
+            .preprocessor
+            .iter()
+            .flatten()
+            .map(|(name, preprocessor)| {
+                let command = match &preprocessor.command {
+                    Some(command) => command.clone(),
+                    None => format!("belong-{}", name),
+                };
+                (name.as_str(), command)
+            })
+            .collect()
     }
 
     /// Get a mutable reference to the project title.
@@ -203,7 +366,114 @@ mod tests {
                     [plugin]
                     another = 5
                 },
+                ..Default::default()
             }
         );
     }
+
+    #[test]
+    fn config_base_url_trailing_slash() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.base_url(), None);
+        config.inner.project.base_url = Some("https://example.com".to_string());
+        assert_eq!(config.base_url().unwrap(), "https://example.com/");
+        config.inner.project.base_url = Some("https://example.com/blog/".to_string());
+        assert_eq!(config.base_url().unwrap(), "https://example.com/blog/");
+    }
+
+    #[test]
+    fn raw_config_from_str_build() {
+        let content = r#"
+            [build]
+            src_dir = "pages"
+            output_dir = "../public"
+        "#;
+        let raw_config: RawConfig = toml::from_str(content).unwrap();
+        assert_eq!(
+            raw_config,
+            RawConfig {
+                build: BuildConfig {
+                    src_dir: Some("pages".into()),
+                    output_dir: Some("../public".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn config_dirs() {
+        let root_dir: PathBuf = ["/", "home", "blog"].iter().collect();
+        let mut config = Config::new(root_dir.clone());
+        assert_eq!(config.src_dir(), root_dir.join("src"));
+        assert_eq!(config.theme_dir(), root_dir.join("theme"));
+        assert_eq!(config.output_dir(), root_dir.join("output"));
+
+        config.inner.build = BuildConfig {
+            src_dir: Some("pages".into()),
+            theme_dir: Some(["/", "themes", "simple"].iter().collect()),
+            output_dir: Some(["..", "public"].iter().collect()),
+            ..Default::default()
+        };
+        assert_eq!(config.src_dir(), root_dir.join("pages"));
+        assert_eq!(
+            config.theme_dir(),
+            ["/", "themes", "simple"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(
+            config.output_dir(),
+            ["/", "home", "public"].iter().collect::<PathBuf>()
+        );
+    }
+
+    #[test]
+    fn config_to_toml_vec_without_build() {
+        let mut config = Config::new(PathBuf::new());
+        *config.title_mut() = Some("My Blog".to_string());
+        assert_eq!(
+            String::from_utf8(config.to_toml_vec().unwrap()).unwrap(),
+            "[project]\ntitle = \"My Blog\"\n"
+        );
+    }
+
+    #[test]
+    fn config_preprocessors() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.preprocessors(), Vec::new());
+        config.inner = toml::from_str(
+            r#"
+            [preprocessor.issues]
+            command = "python3 issues.py"
+            repo = "rossmacarthur/belong"
+
+            [preprocessor.emoji]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.preprocessors(),
+            vec![
+                ("emoji", "belong-emoji".to_string()),
+                ("issues", "python3 issues.py".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn config_taxonomies() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.taxonomies(), vec!["tags", "categories"]);
+        config.inner = toml::from_str(
+            r#"
+            [[taxonomies]]
+            name = "tags"
+
+            [[taxonomies]]
+            name = "series"
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.taxonomies(), vec!["tags", "series"]);
+    }
 }
@@ -0,0 +1,249 @@
+//! Run external preprocessor commands configured in `belong.toml`.
+//!
+//! This follows mdBook's preprocessor protocol. Each command is first run as
+//! `<command> supports html` and is skipped if it exits with a non-zero status.
+//! Otherwise it is run with the context and the project as a JSON array on
+//! stdin, and it must write the project, with any modified pages, as JSON to
+//! stdout. Pages that the preprocessor leaves out are removed from the build.
+//! Commands are run using the platform's shell.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use serde_json::json;
+
+use crate::app::{FrontMatter, Page};
+use crate::config::Config;
+use crate::prelude::*;
+
+/////////////////////////////////////////////////////////////////////////
+// External definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// A page as it is sent to and received from a preprocessor.
+#[derive(Debug, Deserialize, Serialize)]
+struct ExternalPage {
+    /// The location of the page's source file relative to the `src` directory.
+    path: PathBuf,
+    /// Front matter for the page.
+    front_matter: FrontMatter,
+    /// The contents of the page.
+    contents: String,
+}
+
+/// The project as it is sent to and received from a preprocessor.
+#[derive(Debug, Deserialize, Serialize)]
+struct ExternalProject {
+    /// Each of the pages in the project.
+    pages: Vec<ExternalPage>,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// External implementations
+/////////////////////////////////////////////////////////////////////////
+
+/// Build a `Command` that runs a command line using the platform's shell, so
+/// that arguments can be quoted.
+fn command(config: &Config, command_line: &str) -> Result<Command> {
+    if command_line.trim().is_empty() {
+        return Err(anyhow!("command is empty"));
+    }
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command_line).current_dir(config.root_dir());
+    Ok(cmd)
+}
+
+/// Whether the preprocessor supports the HTML renderer.
+fn supports(config: &Config, command_line: &str) -> Result<bool> {
+    let status = command(config, &format!("{} supports html", command_line))?
+        .stdin(Stdio::null())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("failed to run `{}`", command_line))?;
+    Ok(status.success())
+}
+
+/// Pipe the pages through a single preprocessor.
+fn run(config: &Config, name: &str, command_line: &str, pages: Vec<Page>) -> Result<Vec<Page>> {
+    let project = ExternalProject {
+        pages: pages
+            .iter()
+            .map(|page| ExternalPage {
+                path: page.path.clone(),
+                front_matter: page.front_matter.clone(),
+                contents: page.contents.clone(),
+            })
+            .collect(),
+    };
+    let context = json!({
+        "root": config.root_dir(),
+        "config": config.as_context(),
+        "renderer": "html",
+        "belong_version": env!("CARGO_PKG_VERSION"),
+        "preprocessor": name,
+    });
+    let input = json::to_vec(&json!([context, project]))?;
+
+    let mut child = command(config, command_line)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to run `{}`", command_line))?;
+    // Write on another thread so that a preprocessor that starts writing before
+    // reading all of its input does not block forever.
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("failed to wait for `{}`", command_line))?;
+    // The preprocessor is allowed to exit without reading its input.
+    writer.join().unwrap().ok();
+    if !output.status.success() {
+        return Err(anyhow!("`{}` exited with {}", command_line, output.status));
+    }
+    let project: ExternalProject =
+        json::from_slice(&output.stdout).context("failed to parse preprocessor output")?;
+
+    // Keep track of the included files for pages that still exist, so that
+    // they are still rebuilt when the included files change.
+    let mut pages = pages;
+    Ok(project
+        .pages
+        .into_iter()
+        .map(|page| {
+            let previous = pages
+                .iter()
+                .position(|p| p.path == page.path)
+                .map(|i| pages.swap_remove(i))
+                .unwrap_or_default();
+            Page {
+                path: page.path,
+                front_matter: page.front_matter,
+                contents: page.contents,
+                ..previous
+            }
+        })
+        .collect())
+}
+
+/// Pipe the pages through each of the preprocessors configured in the
+/// `Config`.
+pub fn preprocess(config: &Config, mut pages: Vec<Page>) -> Result<Vec<Page>> {
+    for (name, command_line) in config.preprocessors() {
+        if !supports(config, &command_line)
+            .with_context(|| format!("failed to run preprocessor `{}`", name))?
+        {
+            log::warn!("preprocessor `{}` does not support the html renderer", name);
+            continue;
+        }
+        pages = run(config, name, &command_line, pages)
+            .with_context(|| format!("failed to run preprocessor `{}`", name))?;
+    }
+    Ok(pages)
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    fn write_script(path: &Path, script: &str) {
+        fs::write(path, script).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn load_config(root_dir: &Path, toml: &str) -> Config {
+        fs::write(root_dir.join("belong.toml"), toml).unwrap();
+        Config::new(root_dir.to_path_buf()).load().unwrap()
+    }
+
+    fn page(path: &str, contents: &str) -> Page {
+        Page {
+            path: path.into(),
+            contents: contents.into(),
+            includes: vec![PathBuf::from("/included.md")],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn preprocess_replaces_pages() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path();
+        fs::create_dir(root_dir.join("my scripts")).unwrap();
+        write_script(
+            &root_dir.join("my scripts").join("replace.sh"),
+            r#"#!/bin/sh
+if [ "$1" = "supports" ]; then exit 0; fi
+cat > input.json
+echo '{"pages": [{"path": "a.md", "front_matter": {"title": "A"}, "contents": "new"}]}'
+"#,
+        );
+        let config = load_config(
+            root_dir,
+            "[preprocessor.replace]\ncommand = \"'./my scripts/replace.sh'\"\nanswer = 42\n",
+        );
+
+        let pages = preprocess(&config, vec![page("a.md", "old"), page("b.md", "b")]).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].path, PathBuf::from("a.md"));
+        assert_eq!(pages[0].contents, "new");
+        assert_eq!(pages[0].includes, vec![PathBuf::from("/included.md")]);
+
+        let input: json::Value =
+            json::from_slice(&fs::read(root_dir.join("input.json")).unwrap()).unwrap();
+        assert_eq!(input[0]["renderer"], "html");
+        assert_eq!(input[0]["config"]["preprocessor"]["replace"]["answer"], 42);
+        assert_eq!(input[1]["pages"][1]["path"], "b.md");
+        assert_eq!(input[1]["pages"][1]["contents"], "b");
+    }
+
+    #[test]
+    fn preprocess_skips_unsupported() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path();
+        write_script(&root_dir.join("nope.sh"), "#!/bin/sh\nexit 1\n");
+        let config = load_config(root_dir, "[preprocessor.nope]\ncommand = \"./nope.sh\"\n");
+
+        let pages = preprocess(&config, vec![page("a.md", "old")]).unwrap();
+        assert_eq!(pages[0].contents, "old");
+    }
+
+    #[test]
+    fn preprocess_fails() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path();
+        write_script(
+            &root_dir.join("fail.sh"),
+            "#!/bin/sh\nif [ \"$1\" = \"supports\" ]; then exit 0; fi\nexit 3\n",
+        );
+        let config = load_config(root_dir, "[preprocessor.fail]\ncommand = \"./fail.sh\"\n");
+
+        let err = preprocess(&config, vec![page("a.md", "old")]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to run preprocessor `fail`: `./fail.sh` exited with exit status: 3"
+        );
+    }
+}
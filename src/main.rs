@@ -1,13 +1,22 @@
 mod app;
+mod check;
 mod config;
+mod external;
 mod output;
+mod paginate;
 mod prelude;
 mod preprocess;
 mod renderer;
+mod section;
+mod serve;
+mod taxonomy;
 mod theme;
 mod util;
+mod watch;
 
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
 
 use anyhow::Context;
@@ -16,6 +25,10 @@ use casual::{confirm, prompt};
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
+use crate::config::Config;
+use crate::prelude::*;
+use crate::preprocess::Directives;
+
 #[derive(Debug, StructOpt)]
 enum Command {
     /// Initialize a new project.
@@ -25,6 +38,31 @@ enum Command {
         /// Opens the compiled project in the default web browser.
         #[structopt(long)]
         open: bool,
+        /// Keep running and rebuild the project whenever it changes.
+        #[structopt(long, short)]
+        watch: bool,
+        /// Include pages marked as drafts.
+        #[structopt(long)]
+        drafts: bool,
+        /// Check that all links inside the project resolve after building.
+        #[structopt(long)]
+        check_links: bool,
+    },
+    /// Build the project without writing it and check that all links inside
+    /// the project resolve.
+    Check {
+        /// Include pages marked as drafts.
+        #[structopt(long)]
+        drafts: bool,
+    },
+    /// Serve the project locally and rebuild it whenever it changes.
+    Serve {
+        /// The port to serve the project on.
+        #[structopt(long, short, default_value = "3000")]
+        port: u16,
+        /// Opens the served project in the default web browser.
+        #[structopt(long)]
+        open: bool,
     },
 }
 
@@ -37,6 +75,21 @@ enum Command {
     ],
 )]
 struct Opt {
+    /// The project's root directory. Defaults to the first directory
+    /// containing a `belong.toml` file, searching upwards from the current
+    /// directory.
+    #[structopt(
+        long,
+        short = "C",
+        global = true,
+        value_name = "DIR",
+        parse(from_os_str)
+    )]
+    root: Option<PathBuf>,
+    /// The config file to use. Defaults to `belong.toml` in the project's root
+    /// directory.
+    #[structopt(long, global = true, value_name = "FILE", parse(from_os_str))]
+    config: Option<PathBuf>,
     #[structopt(subcommand)]
     command: Command,
 }
@@ -49,7 +102,7 @@ fn title() -> String {
 /// Retrieve a user name from Git.
 fn git_config_user_name() -> Option<String> {
     let output = process::Command::new("git")
-        .args(&["config", "--get", "user.name"])
+        .args(["config", "--get", "user.name"])
         .output()
         .ok()?;
     if output.status.success() {
@@ -70,10 +123,45 @@ fn author() -> String {
     }
 }
 
-fn run() -> anyhow::Result<()> {
-    let Opt { command } = Opt::from_args();
-    femme::with_level(femme::LevelFilter::Debug);
+/// Make a path absolute and canonical, if possible.
+fn absolute(current_dir: &Path, path: PathBuf) -> PathBuf {
+    let path = current_dir.join(path);
+    fs::canonicalize(&path).unwrap_or_else(|_| util::normalize_path(&path))
+}
+
+/// Determine the project's root directory and config file.
+///
+/// If neither is given then we search upwards from the current directory for
+/// a directory containing a `belong.toml` file, unless `search` is false.
+fn locate(root: Option<PathBuf>, config_path: Option<PathBuf>, search: bool) -> Result<Config> {
     let current_dir = env::current_dir().context("could not determine current directory")?;
+    let config_path = config_path.map(|path| absolute(&current_dir, path));
+    let root_dir = match (root, &config_path) {
+        (Some(root), _) => absolute(&current_dir, root),
+        (None, Some(path)) => path.parent().unwrap().to_path_buf(),
+        (None, None) if search => current_dir
+            .ancestors()
+            .find(|dir| dir.join("belong.toml").is_file())
+            .unwrap_or(&current_dir)
+            .to_path_buf(),
+        (None, None) => current_dir,
+    };
+    let mut config = Config::new(root_dir);
+    if let Some(path) = config_path {
+        *config.path_mut() = path;
+    }
+    Ok(config)
+}
+
+fn run() -> Result<()> {
+    let Opt {
+        root,
+        config,
+        command,
+    } = Opt::from_args();
+    femme::with_level(femme::LevelFilter::Debug);
+    let search = !matches!(command, Command::Init);
+    let mut config = locate(root, config, search)?;
 
     match command {
         Command::Init => {
@@ -82,7 +170,8 @@ fn run() -> anyhow::Result<()> {
                  get started:\n"
             );
 
-            app::Builder::new(current_dir)
+            app::Builder::new(config.root_dir())
+                .config_path(config.path())
                 .title(title())
                 .author(author())
                 .gitignore(confirm("Would you like a .gitignore file to be created?"))
@@ -93,19 +182,43 @@ fn run() -> anyhow::Result<()> {
                 "\nAll done! ✨ 🍰 ✨\n\nRun `belong build --open` to build and open the project."
             )
         }
-        Command::Build { open } => {
-            let project = app::Project::from_path(current_dir)
-                .context("failed to load project")?
-                .preprocess()
-                .context("failed to preprocess project")?;
-            let output = project.render().context("failed to render project")?;
-            output
-                .to_path()
-                .context("failed to write rendered project")?;
+        Command::Build {
+            open,
+            watch,
+            drafts,
+            check_links,
+        } => {
+            *config.drafts_mut() = drafts;
+            let mut build = app::Incremental::new(config, Directives::default());
+            let output = build.build()?;
+            if check_links {
+                check::check(&output)?;
+            }
             if open {
                 open::that(output.config().output_dir().join("index.html"))
                     .context("failed to open web page in browser")?;
             }
+            if watch {
+                log::info!("watching project for changes");
+                watch::rebuild_on_change(&mut build, |_| {
+                    log::info!("rebuilt project");
+                })?;
+            }
+        }
+        Command::Check { drafts } => {
+            *config.drafts_mut() = drafts;
+            let output = app::Project::from_config(&config)
+                .context("failed to load project")?
+                .preprocess(&Directives::default())
+                .context("failed to preprocess project")?
+                .render()
+                .context("failed to render project")?;
+            check::check(&output)?;
+        }
+        Command::Serve { port, open } => {
+            *config.drafts_mut() = true;
+            let config = config.load().context("failed to load config")?;
+            serve::serve(config, port, open).context("failed to serve project")?;
         }
     }
 
@@ -2,7 +2,8 @@
 
 use std::borrow::Cow;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 
 use crate::config::Config;
 use crate::prelude::*;
@@ -12,12 +13,47 @@ use crate::util;
 // Output definitions
 /////////////////////////////////////////////////////////////////////////
 
+/// The contents of a rendered file.
+#[derive(Debug, PartialEq)]
+enum Contents {
+    /// UTF-8 text, for example a rendered page.
+    Text(Cow<'static, str>),
+    /// Raw bytes.
+    Bytes(Cow<'static, [u8]>),
+    /// Copy the file at this path, the file is only read when written to the
+    /// output directory.
+    Copy(PathBuf),
+}
+
+/// A link or image in a page's source file.
+#[derive(Debug, PartialEq)]
+pub struct Link {
+    /// The destination as it is written in the source file.
+    pub url: String,
+    /// The final destination in the rendered file.
+    pub resolved: String,
+    /// The line in the source file that the link is on.
+    pub line: usize,
+}
+
+/// The page that a rendered file was generated from.
+#[derive(Debug, PartialEq)]
+pub struct Source {
+    /// The location of the page relative to the src directory.
+    pub path: PathBuf,
+    /// Every link and image in the page's source file.
+    pub links: Vec<Link>,
+}
+
 /// A rendered file.
+#[derive(Debug, PartialEq)]
 pub struct File {
     /// The location of the output file relative to the output directory.
     path: PathBuf,
-    /// The raw contents of the file.
-    contents: Cow<'static, str>,
+    /// The contents of the file.
+    contents: Contents,
+    /// The page this file was generated from, if any.
+    source: Option<Source>,
 }
 
 /// Represents the entire output of our project.
@@ -33,13 +69,108 @@ pub struct Output {
 /////////////////////////////////////////////////////////////////////////
 
 impl File {
+    /// Create a new text `File`.
     pub fn new<S>(path: PathBuf, contents: S) -> Self
     where
         S: Into<Cow<'static, str>>,
     {
-        let contents = contents.into();
-        Self { path, contents }
+        let contents = Contents::Text(contents.into());
+        Self {
+            path,
+            contents,
+            source: None,
+        }
+    }
+
+    /// Create a new binary `File`.
+    #[allow(dead_code)]
+    pub fn from_bytes<B>(path: PathBuf, contents: B) -> Self
+    where
+        B: Into<Cow<'static, [u8]>>,
+    {
+        let contents = Contents::Bytes(contents.into());
+        Self {
+            path,
+            contents,
+            source: None,
+        }
+    }
+
+    /// Create a new `File` that is a copy of the given source file.
+    pub fn copy(path: PathBuf, src: PathBuf) -> Self {
+        let contents = Contents::Copy(src);
+        Self {
+            path,
+            contents,
+            source: None,
+        }
+    }
+
+    /// Set the page this file was generated from.
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The location of the output file relative to the output directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The contents of the file, if it is text.
+    pub fn text(&self) -> Option<&str> {
+        match &self.contents {
+            Contents::Text(text) => Some(text),
+            _ => None,
+        }
     }
+
+    /// The page this file was generated from, if any.
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    /// Write this `File` to the given destination.
+    fn write(&self, dst: &Path) -> Result<()> {
+        match &self.contents {
+            Contents::Text(text) => fs::write(dst, text.as_bytes())
+                .with_context(|| format!("failed to write file `{}`", dst.display())),
+            Contents::Bytes(bytes) => fs::write(dst, bytes)
+                .with_context(|| format!("failed to write file `{}`", dst.display())),
+            Contents::Copy(src) => copy_file(src, dst).with_context(|| {
+                format!(
+                    "failed to copy file `{}` to `{}`",
+                    src.display(),
+                    dst.display()
+                )
+            }),
+        }
+    }
+}
+
+/// Copy a file, preserving its permissions and modification time.
+///
+/// If the destination already has the same size and modification time then
+/// it is assumed to be the same and it is not copied again.
+fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+    let metadata = fs::metadata(src)?;
+    let modified = metadata.modified()?;
+    if let Ok(existing) = fs::metadata(dst) {
+        if existing.len() == metadata.len() && existing.modified().ok() == Some(modified) {
+            return Ok(());
+        }
+        // The destination could be read-only if it was copied previously.
+        util::remove_file(dst)?;
+    }
+    // This streams the contents. Setting the modification time requires write
+    // access on some platforms, so it is set through the writable handle
+    // before the permissions, which could make the file read-only.
+    let mut reader = fs::File::open(src)?;
+    let mut writer = fs::File::create(dst)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.set_modified(modified)?;
+    writer.set_permissions(metadata.permissions())?;
+    Ok(())
 }
 
 impl Output {
@@ -56,6 +187,11 @@ impl Output {
         &self.config
     }
 
+    /// Get each of the output files.
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+
     /// Add a new `File` to the `Output`.
     pub fn push_file(&mut self, file: File) -> &mut Self {
         self.files.push(file);
@@ -71,13 +207,70 @@ impl Output {
                 output_dir.display()
             )
         })?;
+        self.update_path()
+    }
+
+    /// Write the current `Output` to disk, without removing any existing files
+    /// in the output directory.
+    pub fn update_path(&self) -> Result<()> {
+        let output_dir = self.config.output_dir();
         for file in &self.files {
             let dst = output_dir.join(&file.path);
             let dir = dst.parent().unwrap();
             fs::create_dir_all(dir)
                 .with_context(|| format!("failed to create directory `{}`", dir.display()))?;
-            fs::write(&dst, file.contents.as_ref())
-                .with_context(|| format!("failed to write file `{}`", dst.display()))?;
+            file.write(&dst)?;
+        }
+        Ok(())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn output_to_path() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        let src = root_dir.join("image.png");
+        fs::write(&src, b"\x89PNG")?;
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&src)?
+            .set_modified(modified)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&src, fs::Permissions::from_mode(0o444))?;
+        }
+
+        let mut output = Output::new(Config::new(root_dir.clone()));
+        output
+            .push_file(File::new("index.html".into(), "<html></html>"))
+            .push_file(File::from_bytes("data.bin".into(), &b"\xff\x00"[..]))
+            .push_file(File::copy(["images", "a.png"].iter().collect(), src));
+        output.to_path()?;
+        // Writing again must succeed even though the copy is read-only.
+        output.update_path()?;
+
+        let output_dir = root_dir.join("output");
+        assert_eq!(fs::read(output_dir.join("index.html"))?, b"<html></html>");
+        assert_eq!(fs::read(output_dir.join("data.bin"))?, b"\xff\x00");
+        let dst = output_dir.join("images").join("a.png");
+        assert_eq!(fs::read(&dst)?, b"\x89PNG");
+        assert_eq!(fs::metadata(&dst)?.modified()?, modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&dst)?.permissions().mode() & 0o777, 0o444);
         }
         Ok(())
     }
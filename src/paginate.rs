@@ -0,0 +1,199 @@
+//! Split listing pages like `index.html` into multiple pages.
+
+use std::path::PathBuf;
+
+use serde_json as json;
+use serde_json::json;
+
+use crate::util;
+
+/////////////////////////////////////////////////////////////////////////
+// Paginator definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// A single page of a paginated listing.
+#[derive(Debug, PartialEq)]
+pub struct Pager {
+    /// The location of the rendered page relative to the output directory.
+    pub path: PathBuf,
+    /// The URL path to the root of the project from this page.
+    pub path_to_root: String,
+    /// The `paginator` rendering context for this page.
+    pub context: json::Value,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Paginator implementations
+/////////////////////////////////////////////////////////////////////////
+
+/// Get the URL path of the given page number of a listing.
+///
+/// The first page is always the listing itself, subsequent pages are placed
+/// in a `page` directory next to it. For example `tags/rust.html` is followed
+/// by `tags/rust/page/2/index.html`.
+fn page_path(path: &str, number: usize) -> String {
+    if number == 1 {
+        return path.to_string();
+    }
+    let base = match path.rsplit_once('/') {
+        Some((parent, "index.html")) => format!("{}/", parent),
+        None if path == "index.html" => String::new(),
+        _ => format!("{}/", path.trim_end_matches(".html")),
+    };
+    format!("{}page/{}/index.html", base, number)
+}
+
+/// Get the URL path to the root of the project from the given URL path.
+fn path_to_root(path: &str) -> String {
+    "../".repeat(path.matches('/').count())
+}
+
+/// Sort the given page contexts for a listing, newest pages first and undated
+/// pages last.
+pub fn sort_newest_first(pages: &mut [json::Value]) {
+    pages.sort_by(|a, b| b["meta"]["date"].as_str().cmp(&a["meta"]["date"].as_str()));
+}
+
+/// Split the given items into pages of `paginate_by` items each.
+///
+/// `path` is the location of the listing relative to the output directory.
+/// At least one page is always returned, even if there are no items. If
+/// `paginate_by` is not set then all items are placed on a single page.
+pub fn paginate(
+    items: &[json::Value],
+    paginate_by: Option<usize>,
+    path: &str,
+    pretty_urls: bool,
+) -> Vec<Pager> {
+    let url = |number| util::url_path(&page_path(path, number), pretty_urls);
+    let paginate_by = paginate_by.filter(|&n| n > 0).unwrap_or(items.len()).max(1);
+    let chunks: Vec<_> = if items.is_empty() {
+        vec![items]
+    } else {
+        items.chunks(paginate_by).collect()
+    };
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, items)| {
+            let number = i + 1;
+            let output_path = page_path(path, number);
+            let prev = (number > 1).then(|| url(number - 1));
+            let next = (number < total).then(|| url(number + 1));
+            Pager {
+                path: PathBuf::from(&output_path),
+                path_to_root: path_to_root(&output_path),
+                context: json!({
+                    "current": number,
+                    "total": total,
+                    "path": url(number),
+                    "first": url(1),
+                    "last": url(total),
+                    "prev": prev,
+                    "next": next,
+                    "pages": items,
+                }),
+            }
+        })
+        .collect()
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_path_basic() {
+        assert_eq!(page_path("index.html", 1), "index.html");
+        assert_eq!(page_path("index.html", 2), "page/2/index.html");
+        assert_eq!(page_path("tags/index.html", 3), "tags/page/3/index.html");
+        assert_eq!(
+            page_path("tags/rust.html", 2),
+            "tags/rust/page/2/index.html"
+        );
+    }
+
+    #[test]
+    fn sort_newest_first_basic() {
+        let page = |date| json!({ "meta": { "date": date } });
+        let mut pages = vec![
+            page(json!(null)),
+            page(json!("2020-01-01")),
+            page(json!("2020-02-01")),
+        ];
+        sort_newest_first(&mut pages);
+        assert_eq!(
+            pages,
+            vec![
+                page(json!("2020-02-01")),
+                page(json!("2020-01-01")),
+                page(json!(null))
+            ]
+        );
+    }
+
+    #[test]
+    fn paginate_without_paginate_by() {
+        let items: Vec<_> = (0..5).map(|i| json!(i)).collect();
+        let pagers = paginate(&items, None, "index.html", false);
+        assert_eq!(pagers.len(), 1);
+        assert_eq!(pagers[0].path, PathBuf::from("index.html"));
+        assert_eq!(pagers[0].context["pages"], json!([0, 1, 2, 3, 4]));
+        assert_eq!(pagers[0].context["prev"], json!(null));
+        assert_eq!(pagers[0].context["next"], json!(null));
+    }
+
+    #[test]
+    fn paginate_empty() {
+        let pagers = paginate(&[], Some(2), "index.html", false);
+        assert_eq!(pagers.len(), 1);
+        assert_eq!(pagers[0].context["total"], json!(1));
+        assert_eq!(pagers[0].context["pages"], json!([]));
+    }
+
+    #[test]
+    fn paginate_multiple_pages() {
+        let items: Vec<_> = (0..5).map(|i| json!(i)).collect();
+        let pagers = paginate(&items, Some(2), "index.html", false);
+        assert_eq!(pagers.len(), 3);
+        assert_eq!(
+            pagers[1],
+            Pager {
+                path: ["page", "2", "index.html"].iter().collect(),
+                path_to_root: "../../".into(),
+                context: json!({
+                    "current": 2,
+                    "total": 3,
+                    "path": "page/2/index.html",
+                    "first": "index.html",
+                    "last": "page/3/index.html",
+                    "prev": "index.html",
+                    "next": "page/3/index.html",
+                    "pages": [2, 3],
+                }),
+            }
+        );
+        assert_eq!(pagers[2].context["pages"], json!([4]));
+        assert_eq!(pagers[2].context["next"], json!(null));
+    }
+
+    #[test]
+    fn paginate_pretty_urls() {
+        let items: Vec<_> = (0..3).map(|i| json!(i)).collect();
+        let pagers = paginate(&items, Some(1), "tags/rust/index.html", true);
+        assert_eq!(
+            pagers[1].path,
+            ["tags", "rust", "page", "2", "index.html"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+        assert_eq!(pagers[1].context["path"], json!("tags/rust/page/2/"));
+        assert_eq!(pagers[1].context["prev"], json!("tags/rust/"));
+        assert_eq!(pagers[1].context["next"], json!("tags/rust/page/3/"));
+    }
+}
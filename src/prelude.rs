@@ -2,6 +2,6 @@
 
 pub use std::result;
 
-pub use anyhow::{anyhow, bail, Context, Error, Result};
+pub use anyhow::{anyhow, Context, Error, Result};
 
 pub use crate::util::{FromPath, Join, TomlValueExt};
@@ -1,13 +1,16 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::ops::{Bound, Range, RangeBounds, RangeFrom, RangeFull, RangeTo};
 use std::path::{Path, PathBuf};
 
-use regex::Captures;
+use pulldown_cmark::{Event, Options, Parser, Tag};
 use regex_macro::regex;
 
 use crate::app::Page;
 use crate::config::Config;
 use crate::prelude::*;
+use crate::renderer::{self, Renderer};
 
 /////////////////////////////////////////////////////////////////////////
 // Definitions
@@ -44,17 +47,106 @@ struct Include {
     select: Select,
 }
 
-#[derive(Debug)]
-enum DirectiveKind {
-    Include(Include),
+/// A preprocessing directive, for example `{{ #include listing.rs }}`.
+///
+/// Directives are registered with `Directives` and are expanded in every page
+/// before it is rendered.
+pub trait Directive {
+    /// The parsed arguments of the directive.
+    type Args;
+
+    /// The name of the directive, this follows the `#`.
+    fn name(&self) -> &str;
+
+    /// Parse the arguments given to the directive, this is everything after
+    /// the name.
+    fn parse(&self, args: &str) -> Result<Self::Args>;
+
+    /// Expand the directive in the given page.
+    fn expand(
+        &self,
+        args: Self::Args,
+        config: &Config,
+        page: &Page,
+        ctx: &mut DirectiveContext<'_>,
+    ) -> Result<Expansion>;
+
+    /// Whether the directive is also expanded inside code blocks, defaults to
+    /// false. Directives are never expanded inside code spans.
+    fn in_code(&self) -> bool {
+        false
+    }
+}
+
+/// What a directive is replaced with.
+pub enum Expansion {
+    /// Insert the text as is.
+    Text(String),
+    /// Insert Markdown read from the file at the given full path, expanding any
+    /// directives in it.
+    Markdown(PathBuf, String),
+    /// Insert text generated from the fully expanded page, this is called once
+    /// all the other directives have been expanded.
+    Deferred(Deferred),
 }
 
+/// Generates text from the fully expanded page.
+pub type Deferred = Box<dyn FnOnce(&str) -> String>;
+
+/// Where a directive is being expanded.
 #[derive(Debug)]
-struct Directive<'a> {
-    kind: DirectiveKind,
-    captures: Captures<'a>,
+pub struct DirectiveContext<'a> {
+    /// The full path of the file containing the directive, this is either the
+    /// page or a file included into it.
+    pub path: &'a Path,
+    /// The full paths of the files included into the page.
+    includes: &'a mut Vec<PathBuf>,
+}
+
+/// A directive with its arguments parsed, ready to be expanded.
+type Parsed<'a> =
+    Box<dyn FnOnce(&Config, &Page, &mut DirectiveContext<'_>) -> Result<Expansion> + 'a>;
+
+/// An object safe version of `Directive`.
+trait AnyDirective {
+    /// Parse the arguments given to the directive.
+    fn parse_any<'a>(&'a self, args: &str) -> Result<Parsed<'a>>;
+
+    /// Whether the directive is also expanded inside code blocks.
+    fn in_code_any(&self) -> bool;
+}
+
+/// The directives that can be used in pages, keyed by name.
+pub struct Directives {
+    directives: HashMap<String, Box<dyn AnyDirective>>,
+}
+
+/// What to do with a directive found in some contents.
+enum Action<'a> {
+    /// Expand the directive.
+    Expand(Parsed<'a>),
+    /// The directive is escaped with a backslash, remove the backslash and
+    /// leave the rest as is.
+    Unescape,
+}
+
+/// A directive found in some contents.
+struct Invocation<'a> {
+    /// What to do with the directive.
+    action: Action<'a>,
+    /// The location of the directive in the contents.
+    range: Range<usize>,
 }
 
+/// Includes the selected part of a file, see `Include`.
+struct IncludeDirective;
+
+/// Includes a whole file, hiding the lines outside of the selection.
+struct RustdocIncludeDirective;
+
+/// Inserts a table of contents.
+struct TocDirective;
+
 /////////////////////////////////////////////////////////////////////////
 // Implementations
 /////////////////////////////////////////////////////////////////////////
@@ -164,6 +256,55 @@ fn extract_line_range(contents: String, line_range: LineRange) -> String {
     .join("\n")
 }
 
+/// Like `extract_anchor` but lines outside of the anchor are kept and
+/// prefixed with `# ` so that they are hidden.
+fn extract_rustdoc_anchor(contents: String, anchor: String) -> String {
+    let start = regex!(r"ANCHOR:\s*(?P<name>[\w_-]+)");
+    let end = regex!(r"ANCHOR_END:\s*(?P<name>[\w_-]+)");
+    let mut retained = Vec::new();
+    let mut found = false;
+    for line in contents.lines() {
+        if found {
+            match end.captures(line) {
+                Some(captures) => {
+                    if captures["name"] == anchor {
+                        found = false;
+                    }
+                }
+                None => {
+                    if !start.is_match(line) {
+                        retained.push(line.to_string());
+                    }
+                }
+            }
+        } else if let Some(captures) = start.captures(line) {
+            if captures["name"] == anchor {
+                found = true;
+            }
+        } else if !end.is_match(line) {
+            retained.push(format!("# {}", line));
+        }
+    }
+    retained.join("\n")
+}
+
+/// Like `extract_line_range` but lines outside of the range are kept and
+/// prefixed with `# ` so that they are hidden.
+fn extract_rustdoc_line_range(contents: String, line_range: LineRange) -> String {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if line_range.contains(&i) {
+                line.to_string()
+            } else {
+                format!("# {}", line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl Include {
     fn from_str(args: &str) -> Result<Self> {
         let (path, select) = if args.contains('@') {
@@ -180,87 +321,411 @@ impl Include {
         Ok(Self { path, select })
     }
 
-    fn read(self, page_path: &Path) -> Result<String> {
-        let Self { path, select } = self;
-        let path = page_path.parent().unwrap().join(path);
+    /// Read the included file, returning its full path as well as its
+    /// contents.
+    fn read_file(&self, page_path: &Path) -> Result<(PathBuf, String)> {
+        let path = page_path.parent().unwrap().join(&self.path);
         let contents = fs::read_to_string(&path)
             .with_context(|| format!("failed to read from `{}`", path.display()))?;
-        Ok(match select {
+        let path = fs::canonicalize(&path).unwrap_or(path);
+        Ok((path, contents))
+    }
+
+    /// Read the selected text, returning the full path of the included file as
+    /// well as the text.
+    fn read(self, page_path: &Path) -> Result<(PathBuf, String)> {
+        let (path, contents) = self.read_file(page_path)?;
+        let text = match self.select {
             Select::Anchor(anchor) => extract_anchor(contents, anchor),
             Select::LineRange(line_range) => extract_line_range(contents, line_range),
-        })
+        };
+        Ok((path, text))
+    }
+
+    /// Read the whole file, hiding the text that is not selected, returning
+    /// the full path of the included file as well as the text.
+    fn read_rustdoc(self, page_path: &Path) -> Result<(PathBuf, String)> {
+        let (path, contents) = self.read_file(page_path)?;
+        let text = match self.select {
+            Select::Anchor(anchor) => extract_rustdoc_anchor(contents, anchor),
+            Select::LineRange(line_range) => extract_rustdoc_line_range(contents, line_range),
+        };
+        Ok((path, text))
     }
 }
 
-impl Directive<'_> {
-    fn range(&self) -> (usize, usize) {
-        let m = self.captures.get(0).unwrap();
-        (m.start(), m.end())
+impl<D: Directive> AnyDirective for D {
+    fn parse_any<'a>(&'a self, args: &str) -> Result<Parsed<'a>> {
+        let args = self.parse(args)?;
+        Ok(Box::new(move |config, page, ctx| {
+            self.expand(args, config, page, ctx)
+        }))
+    }
+
+    fn in_code_any(&self) -> bool {
+        self.in_code()
     }
 }
 
-fn find_directives(contents: &str) -> Result<Vec<Directive>> {
-    let re = regex!(r"\{\{\s*#(?P<name>[a-zA-Z0-9_]+)\s+((?P<args>.*)\s*)\}\}");
-    let mut directives = Vec::new();
-    for captures in re.captures_iter(contents) {
-        let name = &captures["name"];
-        let args = &captures["args"];
-        match name {
-            "include" => match Include::from_str(args) {
-                Ok(include) => {
-                    let kind = DirectiveKind::Include(include);
-                    directives.push(Directive { kind, captures })
-                }
-                err => log::warn!(
-                    "{:?}\n",
-                    err.with_context(|| format!(
-                        "failed to parse include directive `{}`",
-                        captures.get(0).unwrap().as_str()
-                    ))
-                    .unwrap_err()
-                ),
-            },
-            name => log::warn!("unrecognized directive `{}`", name),
+impl DirectiveContext<'_> {
+    /// Record that the page includes the file at the given full path, the
+    /// page is rebuilt whenever this file changes.
+    pub fn add_include(&mut self, path: PathBuf) {
+        self.includes.push(path);
+    }
+}
+
+impl Directive for IncludeDirective {
+    type Args = Include;
+
+    fn name(&self) -> &str {
+        "include"
+    }
+
+    fn parse(&self, args: &str) -> Result<Self::Args> {
+        Include::from_str(args)
+    }
+
+    fn in_code(&self) -> bool {
+        // Including listings into code blocks is the most common use.
+        true
+    }
+
+    fn expand(
+        &self,
+        include: Include,
+        _: &Config,
+        _: &Page,
+        ctx: &mut DirectiveContext<'_>,
+    ) -> Result<Expansion> {
+        let (path, text) = include.read(ctx.path)?;
+        ctx.add_include(path.clone());
+        if path.extension().is_some_and(|ext| ext == "md") {
+            Ok(Expansion::Markdown(path, text))
+        } else {
+            Ok(Expansion::Text(text))
+        }
+    }
+}
+
+impl Directive for RustdocIncludeDirective {
+    type Args = Include;
+
+    fn name(&self) -> &str {
+        "rustdoc_include"
+    }
+
+    fn parse(&self, args: &str) -> Result<Self::Args> {
+        Include::from_str(args)
+    }
+
+    fn in_code(&self) -> bool {
+        true
+    }
+
+    fn expand(
+        &self,
+        include: Include,
+        _: &Config,
+        _: &Page,
+        ctx: &mut DirectiveContext<'_>,
+    ) -> Result<Expansion> {
+        let (path, text) = include.read_rustdoc(ctx.path)?;
+        ctx.add_include(path);
+        Ok(Expansion::Text(text))
+    }
+}
+
+impl Directive for TocDirective {
+    type Args = ();
+
+    fn name(&self) -> &str {
+        "toc"
+    }
+
+    fn parse(&self, _: &str) -> Result<Self::Args> {
+        Ok(())
+    }
+
+    fn expand(
+        &self,
+        _: (),
+        _: &Config,
+        _: &Page,
+        _: &mut DirectiveContext<'_>,
+    ) -> Result<Expansion> {
+        // The table of contents can only be generated once all the other
+        // directives have been expanded.
+        Ok(Expansion::Deferred(Box::new(|contents| {
+            renderer::toc_html(&Renderer::new(contents).toc())
+        })))
+    }
+}
+
+impl Default for Directives {
+    /// Create a new `Directives` containing the built-in directives.
+    fn default() -> Self {
+        let mut directives = Self {
+            directives: HashMap::new(),
         };
+        directives
+            .register(IncludeDirective)
+            .register(RustdocIncludeDirective)
+            .register(TocDirective);
+        directives
     }
-    Ok(directives)
-}
-
-fn preprocess(config: &Config, path: &Path, contents: &str) -> Result<String> {
-    let mut new_contents = String::new();
-    let mut previous_end = 0;
-    for directive in find_directives(&contents)? {
-        let (start, end) = directive.range();
-        new_contents.push_str(&contents[previous_end..start]);
-        match directive {
-            Directive {
-                kind: DirectiveKind::Include(include),
-                ..
-            } => {
-                let page_path = config.src_dir().join(&path);
-                new_contents.push_str(&include.read(&page_path)?);
+}
+
+impl fmt::Debug for Directives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.directives.keys()).finish()
+    }
+}
+
+impl Directives {
+    /// Register a directive, replacing any directive with the same name.
+    pub fn register<D>(&mut self, directive: D) -> &mut Self
+    where
+        D: Directive + 'static,
+    {
+        self.directives
+            .insert(directive.name().to_string(), Box::new(directive));
+        self
+    }
+
+    /// Find and parse all the directives in the given contents.
+    ///
+    /// The contents are parsed as Markdown and directives are only searched
+    /// for in the text, HTML, and code blocks, using the parser's offsets to
+    /// locate them. Directives inside code spans are left as is, and
+    /// directives inside code blocks are only expanded if they opt in.
+    ///
+    /// Directives escaped with a backslash, for example `\{{ #include }}`, are
+    /// not expanded, the backslash is removed wherever the directive would
+    /// otherwise be expanded. Unrecognized directives and directives with
+    /// invalid arguments are left as is.
+    fn find<'a>(&'a self, contents: &str) -> Vec<Invocation<'a>> {
+        let re = regex!(r"\{\{\s*#(?P<name>[a-zA-Z0-9_]+)\s*((?P<args>.*?)\s*)\}\}");
+        let mut invocations = Vec::new();
+        for (text, in_code) in text_ranges(contents) {
+            for captures in re.captures_iter(&contents[text.clone()]) {
+                let m = captures.get(0).unwrap();
+                let range = text.start + m.start()..text.start + m.end();
+                let escaped = contents[..range.start].ends_with('\\');
+                let name = &captures["name"];
+                let directive = match self.directives.get(name) {
+                    Some(directive) if in_code && !directive.in_code_any() => continue,
+                    Some(directive) => directive,
+                    None if in_code || escaped => continue,
+                    None => {
+                        log::warn!("unrecognized directive `{}`", name);
+                        continue;
+                    }
+                };
+                if escaped {
+                    let action = Action::Unescape;
+                    let range = range.start - 1..range.end;
+                    invocations.push(Invocation { action, range });
+                    continue;
+                }
+                match directive.parse_any(&captures["args"]) {
+                    Ok(parsed) => {
+                        let action = Action::Expand(parsed);
+                        invocations.push(Invocation { action, range })
+                    }
+                    err => log::warn!(
+                        "{:?}\n",
+                        err.with_context(|| format!(
+                            "failed to parse {} directive `{}`",
+                            name, &contents[range]
+                        ))
+                        .err()
+                        .unwrap()
+                    ),
+                }
             }
         }
-        previous_end = end;
+        invocations
     }
-    new_contents.push_str(&contents[previous_end..]);
-    Ok(new_contents)
+}
+
+/// Returns the locations of the text in the given Markdown that directives
+/// are searched for in, and whether each is inside a code block.
+///
+/// The parser splits text at things like emphasis and backslash escapes, so
+/// adjacent inline events are combined into a single location. Code spans are
+/// left out entirely.
+fn text_ranges(contents: &str) -> Vec<(Range<usize>, bool)> {
+    let mut ranges = Vec::new();
+    let mut inline: Option<Range<usize>> = None;
+    let mut in_code = false;
+    for (event, range) in Parser::new_ext(contents, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+            Event::Text(_) if in_code => ranges.push((range, true)),
+            Event::Text(_)
+            | Event::Html(_)
+            | Event::SoftBreak
+            | Event::HardBreak
+            | Event::Start(Tag::Emphasis)
+            | Event::End(Tag::Emphasis)
+            | Event::Start(Tag::Strong)
+            | Event::End(Tag::Strong)
+            | Event::Start(Tag::Strikethrough)
+            | Event::End(Tag::Strikethrough)
+            | Event::Start(Tag::Link(..))
+            | Event::End(Tag::Link(..))
+            | Event::Start(Tag::Image(..))
+            | Event::End(Tag::Image(..)) => {
+                inline = Some(match inline {
+                    Some(inline) => inline.start.min(range.start)..inline.end.max(range.end),
+                    None => range,
+                });
+                continue;
+            }
+            _ => {}
+        }
+        ranges.extend(inline.take().map(|inline| (inline, false)));
+    }
+    ranges.extend(inline.map(|inline| (inline, false)));
+    ranges
+}
+
+/// The maximum depth of nested includes.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Expands directives into a new document, following includes of Markdown
+/// files recursively.
+struct Expander<'a> {
+    /// The project's configuration.
+    config: &'a Config,
+    /// The directives that can be used.
+    directives: &'a Directives,
+    /// The page being preprocessed.
+    page: &'a Page,
+    /// The expanded contents.
+    contents: String,
+    /// The full paths of all the included files.
+    includes: Vec<PathBuf>,
+    /// The deferred directives and their locations in the expanded contents.
+    deferred: Vec<(Range<usize>, Deferred)>,
+    /// The full paths of the files currently being expanded, starting with the
+    /// page.
+    chain: Vec<PathBuf>,
+}
+
+impl Expander<'_> {
+    /// Format the current include chain, ending with the given path.
+    fn format_chain(&self, path: &Path) -> String {
+        self.chain
+            .iter()
+            .chain(Some(&path.to_path_buf()))
+            .map(|path| format!("`{}`", path.display()))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    /// Expand the directives in the contents of the file at the given full
+    /// path.
+    ///
+    /// Markdown returned by a directive is expanded as well, relative paths in
+    /// it are resolved relative to the file it came from.
+    fn expand(&mut self, path: &Path, contents: &str) -> Result<()> {
+        let mut previous_end = 0;
+        for Invocation { action, range } in self.directives.find(contents) {
+            self.contents.push_str(&contents[previous_end..range.start]);
+            previous_end = range.end;
+            let parsed = match action {
+                Action::Expand(parsed) => parsed,
+                Action::Unescape => {
+                    self.contents
+                        .push_str(&contents[range.start + 1..range.end]);
+                    continue;
+                }
+            };
+            let mut ctx = DirectiveContext {
+                path,
+                includes: &mut self.includes,
+            };
+            match parsed(self.config, self.page, &mut ctx)? {
+                Expansion::Text(text) => self.contents.push_str(&text),
+                Expansion::Markdown(include_path, text) => {
+                    if self.chain.contains(&include_path) {
+                        return Err(anyhow!(
+                            "include cycle detected: {}",
+                            self.format_chain(&include_path)
+                        ));
+                    }
+                    if self.chain.len() > MAX_INCLUDE_DEPTH {
+                        return Err(anyhow!(
+                            "includes are nested more than {} levels deep: {}",
+                            MAX_INCLUDE_DEPTH,
+                            self.format_chain(&include_path)
+                        ));
+                    }
+                    self.chain.push(include_path.clone());
+                    self.expand(&include_path, &text)?;
+                    self.chain.pop();
+                }
+                Expansion::Deferred(f) => {
+                    // Leave the directive in place for now and replace it once
+                    // everything else is expanded.
+                    let start = self.contents.len();
+                    self.contents.push_str(&contents[range.clone()]);
+                    self.deferred.push((start..self.contents.len(), f));
+                }
+            }
+        }
+        self.contents.push_str(&contents[previous_end..]);
+        Ok(())
+    }
+}
+
+/// Expand all the directives in the given page, returning the new contents and
+/// the full paths of all the included files.
+fn preprocess(
+    config: &Config,
+    directives: &Directives,
+    page: &Page,
+) -> Result<(String, Vec<PathBuf>)> {
+    let page_path = config.src_dir().join(&page.path);
+    let mut expander = Expander {
+        config,
+        directives,
+        page,
+        contents: String::new(),
+        includes: Vec::new(),
+        deferred: Vec::new(),
+        chain: vec![fs::canonicalize(&page_path).unwrap_or_else(|_| page_path.clone())],
+    };
+    expander.expand(&page_path, &page.contents)?;
+    let Expander {
+        contents: mut new_contents,
+        includes,
+        deferred,
+        ..
+    } = expander;
+    let expanded: Vec<_> = deferred
+        .into_iter()
+        .map(|(range, f)| (range, f(&new_contents)))
+        .collect();
+    for (range, text) in expanded.into_iter().rev() {
+        new_contents.replace_range(range, &text);
+    }
+    Ok((new_contents, includes))
 }
 
 impl Page {
-    /// Returns a preprocessed version of this `Page`.
-    pub fn preprocess(self, config: &Config) -> Result<Self> {
-        let Self {
-            path,
-            front_matter,
-            contents,
-        } = self;
-        let contents = preprocess(config, &path, &contents)
-            .with_context(|| format!("failed to preprocess page `{}`", path.display()))?;
+    /// Returns a preprocessed version of this `Page`, expanding the given
+    /// directives.
+    pub fn preprocess(self, config: &Config, directives: &Directives) -> Result<Self> {
+        let (contents, includes) = preprocess(config, directives, &self)
+            .with_context(|| format!("failed to preprocess page `{}`", self.path.display()))?;
         Ok(Self {
-            path,
-            front_matter,
             contents,
+            includes,
+            ..self
         })
     }
 }
@@ -367,7 +832,7 @@ fn main() {
         let page = Page::from_path(&root_dir.join("src"), &page_path)?;
         assert_eq!(page.contents, page_contents);
 
-        let page = page.preprocess(&Config::new(root_dir))?;
+        let page = page.preprocess(&Config::new(root_dir), &Directives::default())?;
         assert_eq!(
             page.contents,
             r#"
@@ -412,7 +877,7 @@ fn main() {
         let page = Page::from_path(&root_dir.join("src"), &page_path)?;
         assert_eq!(page.contents, page_contents);
 
-        let page = page.preprocess(&Config::new(root_dir))?;
+        let page = page.preprocess(&Config::new(root_dir), &Directives::default())?;
         assert_eq!(
             page.contents,
             r#"
@@ -427,4 +892,266 @@ fn main() {
 
         Ok(())
     }
+
+    #[test]
+    fn include_extract_rustdoc() {
+        let contents =
+            "use std::io;\n// ANCHOR: main\nfn main() {}\n// ANCHOR_END: main\nfn other() {}";
+        assert_eq!(
+            extract_rustdoc_anchor(contents.into(), "main".into()),
+            "# use std::io;\nfn main() {}\n# fn other() {}",
+        );
+        assert_eq!(
+            extract_rustdoc_line_range("line 1\nline 2\nline 3".into(), LineRange::Range(1..2)),
+            "# line 1\nline 2\n# line 3",
+        );
+    }
+
+    #[test]
+    fn page_preprocess_include_nested() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root_dir.join("src"))?;
+        fs::create_dir_all(root_dir.join("shared").join("code"))?;
+        let page_path = root_dir.join("src").join("page.md");
+        fs::write(&page_path, "Start\n{{#include ../shared/intro.md}}\nEnd\n")?;
+        fs::write(
+            root_dir.join("shared").join("intro.md"),
+            "Intro\n```rust\n{{#include code/listing.rs}}\n```",
+        )?;
+        fs::write(
+            root_dir.join("shared").join("code").join("listing.rs"),
+            "// {{#include other.md}}\nfn main() {}",
+        )?;
+
+        let page = Page::from_path(&root_dir.join("src"), &page_path)?;
+        let page = page.preprocess(&Config::new(root_dir), &Directives::default())?;
+        assert_eq!(
+            page.contents,
+            "Start\nIntro\n```rust\n// {{#include other.md}}\nfn main() {}\n```\nEnd\n"
+        );
+        assert_eq!(page.includes.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_preprocess_include_cycle() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root_dir.join("src"))?;
+        let page_path = root_dir.join("src").join("page.md");
+        fs::write(&page_path, "{{#include a.md}}")?;
+        fs::write(root_dir.join("src").join("a.md"), "{{#include b.md}}")?;
+        fs::write(root_dir.join("src").join("b.md"), "{{#include a.md}}")?;
+
+        let page = Page::from_path(&root_dir.join("src"), &page_path)?;
+        let err = page
+            .preprocess(&Config::new(root_dir), &Directives::default())
+            .unwrap_err();
+        let msg = format!("{:#}", err);
+        assert!(msg.contains("include cycle detected"), "{}", msg);
+        assert!(
+            regex!(r"`[^`]*page\.md` -> `[^`]*a\.md` -> `[^`]*b\.md` -> `[^`]*a\.md`")
+                .is_match(&msg),
+            "{}",
+            msg
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_preprocess_include_too_deep() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root_dir.join("src"))?;
+        let page_path = root_dir.join("src").join("page.md");
+        fs::write(&page_path, "{{#include 0.md}}")?;
+        for i in 0..=MAX_INCLUDE_DEPTH {
+            fs::write(
+                root_dir.join("src").join(format!("{}.md", i)),
+                format!("{{{{#include {}.md}}}}", i + 1),
+            )?;
+        }
+        fs::write(
+            root_dir
+                .join("src")
+                .join(format!("{}.md", MAX_INCLUDE_DEPTH + 1)),
+            "x",
+        )?;
+
+        let page = Page::from_path(&root_dir.join("src"), &page_path)?;
+        let err = page
+            .preprocess(&Config::new(root_dir), &Directives::default())
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("nested more than 10 levels deep"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_preprocess_custom_directive() -> Result<()> {
+        struct Issue;
+
+        impl Directive for Issue {
+            type Args = u32;
+
+            fn name(&self) -> &str {
+                "issue"
+            }
+
+            fn parse(&self, args: &str) -> Result<Self::Args> {
+                args.trim().parse().context("expected an issue number")
+            }
+
+            fn expand(
+                &self,
+                number: u32,
+                _: &Config,
+                page: &Page,
+                _: &mut DirectiveContext<'_>,
+            ) -> Result<Expansion> {
+                Ok(Expansion::Text(format!(
+                    "[#{}](https://github.com/{}/issues/{})",
+                    number,
+                    page.front_matter.terms("repo")[0],
+                    number
+                )))
+            }
+        }
+
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root_dir.join("src"))?;
+        let page_path = root_dir.join("src").join("page.md");
+        fs::write(
+            &page_path,
+            "+++\nrepo = \"a/b\"\n+++\nSee {{#issue 12}} and {{#issue nope}}.\n",
+        )?;
+
+        let mut directives = Directives::default();
+        directives.register(Issue);
+        let page = Page::from_path(&root_dir.join("src"), &page_path)?;
+        let page = page.preprocess(&Config::new(root_dir), &directives)?;
+        assert_eq!(
+            page.contents,
+            "See [#12](https://github.com/a/b/issues/12) and {{#issue nope}}.\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_ranges_basic() {
+        let contents = "Text `{{#toc}}` \\{{#include a*b*.rs}}.\n\n<div>{{#toc}}</div>\n\n\
+                        ```md\n{{#toc}}\n```\n\n    indented\n";
+        let ranges: Vec<_> = text_ranges(contents)
+            .into_iter()
+            .map(|(range, in_code)| (&contents[range], in_code))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                ("Text ", false),
+                (" \\{{#include a*b*.rs}}.", false),
+                ("<div>{{#toc}}</div>\n", false),
+                ("{{#toc}}\n", true),
+                ("indented\n", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn page_preprocess_escapes_and_code() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root_dir.join("src"))?;
+        let page_path = root_dir.join("src").join("page.md");
+        fs::write(
+            &page_path,
+            r#"# Directives
+
+Use `{{ #toc }}` or \{{ #include listing.rs }}.
+
+Inline `{{ #include listing.rs }}` and `\{{ #include listing.rs }}` are left as is.
+
+```md
+{{ #toc }}
+\{{ #toc }}
+\{{ #include listing.rs }}
+{{ #unknown }}
+```
+
+```rust
+{{ #include listing.rs }}
+```
+"#,
+        )?;
+        fs::write(root_dir.join("src").join("listing.rs"), "fn main() {}")?;
+
+        let page = Page::from_path(&root_dir.join("src"), &page_path)?;
+        let page = page.preprocess(&Config::new(root_dir), &Directives::default())?;
+        assert_eq!(
+            page.contents,
+            r#"# Directives
+
+Use `{{ #toc }}` or {{ #include listing.rs }}.
+
+Inline `{{ #include listing.rs }}` and `\{{ #include listing.rs }}` are left as is.
+
+```md
+{{ #toc }}
+\{{ #toc }}
+{{ #include listing.rs }}
+{{ #unknown }}
+```
+
+```rust
+fn main() {}
+```
+"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_preprocess_toc() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root_dir.join("src"))?;
+        let page_path = root_dir.join("src").join("page.md");
+        fs::write(
+            &page_path,
+            "{{#toc}}\n\n# Hello & Welcome\n\n## Details\n\n{{#include ../more.md}}\n",
+        )?;
+        fs::write(root_dir.join("more.md"), "# More")?;
+
+        let page = Page::from_path(&root_dir.join("src"), &page_path)?;
+        let page = page.preprocess(&Config::new(root_dir), &Directives::default())?;
+        assert_eq!(
+            page.contents,
+            r##"<nav class="toc">
+<ul>
+<li><a href="#hello-welcome">Hello &amp; Welcome</a>
+<ul>
+<li><a href="#details">Details</a></li>
+</ul>
+</li>
+<li><a href="#more">More</a></li>
+</ul>
+</nav>
+
+
+# Hello & Welcome
+
+## Details
+
+# More
+"##
+        );
+
+        Ok(())
+    }
 }
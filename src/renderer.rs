@@ -8,9 +8,30 @@
 //! [`pulldown_cmark::html::push_html`]:
 //! ../../pulldown_cmark/html/fn.push_html.html
 
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use once_cell::sync::Lazy;
 use pulldown_cmark::html;
-use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag};
 use regex_macro::regex;
+use serde::Serialize;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use crate::prelude::*;
+use crate::util;
+
+/// The CSS class prefix used for highlighted code.
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// The syntax definitions used for highlighting code blocks.
+static SYNTAXES: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// The available highlighting themes.
+static THEMES: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
 
 /// Fix a URL for HTML rendering.
 ///
@@ -28,37 +49,669 @@ fn fix_markdown_url(url: CowStr) -> CowStr {
     }
 }
 
-/// Fix Markdown links by replacing `.md` with `.html`.
-fn fix_markdown_links(event: Event) -> Event {
+/// Returns a URL path relative to the given directory URL path.
+///
+/// For example `posts/a.html` relative to `tags/rust/` is `../../posts/a.html`.
+fn relative_url(from_dir: &str, to: &str) -> String {
+    let from: Vec<_> = from_dir.split('/').filter(|s| !s.is_empty()).collect();
+    let to: Vec<_> = to.split('/').collect();
+    let (to_dirs, to_file) = to.split_at(to.len() - 1);
+    let common = from.iter().zip(to_dirs).take_while(|(a, b)| a == b).count();
+    let url: String =
+        "../".repeat(from.len() - common) + &[&to_dirs[common..], to_file].concat().join("/");
+    if url.is_empty() {
+        "./".to_string()
+    } else {
+        url
+    }
+}
+
+/// Returns the absolute URL of a link in the page at the given URL path.
+///
+/// For example `../b.html#x` in `posts/a/` is `https://example.com/posts/b.html#x`
+/// when the base URL is `https://example.com/`. Links with a scheme are
+/// returned unchanged.
+fn absolute_url(base_url: &str, url_path: &str, url: &str) -> String {
+    if url.starts_with("//") || regex!(r"^[a-zA-Z][a-zA-Z0-9+.-]*:").is_match(url) {
+        return url.to_string();
+    }
+    if let Some(url) = url.strip_prefix('/') {
+        return format!("{}{}", base_url, url);
+    }
+    let i = url.find(&['?', '#'][..]).unwrap_or(url.len());
+    let (path, rest) = url.split_at(i);
+    if path.is_empty() {
+        return format!("{}{}{}", base_url, url_path, rest);
+    }
+    let mut segments: Vec<_> = url_path.split('/').collect();
+    segments.pop();
+    for segment in path.split('/') {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if matches!(path.rsplit('/').next(), Some(".") | Some("..")) {
+        segments.push("");
+    }
+    format!("{}{}{}", base_url, segments.join("/"), rest)
+}
+
+/// Rewrite the destination of every link and image in rendered HTML to an
+/// absolute URL, for use outside of the page, for example in a feed.
+pub fn absolute_links(html: &str, base_url: &str, url_path: &str) -> String {
+    let re = regex!(r#"(<(?:a\s(?:[^>]*?\s)?href|img\s(?:[^>]*?\s)?src)=")([^"]*)""#);
+    re.replace_all(html, |captures: &regex::Captures| {
+        format!(
+            "{}{}\"",
+            &captures[1],
+            absolute_url(base_url, url_path, &captures[2])
+        )
+    })
+    .into_owned()
+}
+
+/// Get the URL path of a link target that is not a page, this mirrors the
+/// path in the src directory.
+pub fn fallback_url(target: &Path, pretty_urls: bool) -> String {
+    let components: Vec<_> = target.components().map(|c| c.as_os_str()).collect();
+    let path = Join::join(components.as_slice(), "/")
+        .to_string_lossy()
+        .into_owned();
+    match path.strip_suffix(".md") {
+        Some(path) if pretty_urls => {
+            let (dir, stem) = path.split_at(path.rfind('/').map(|i| i + 1).unwrap_or(0));
+            if stem == "index" || stem == "_index" {
+                dir.to_string()
+            } else {
+                format!("{}/", path)
+            }
+        }
+        Some(path) => format!("{}.html", path),
+        None => path,
+    }
+}
+
+/// Resolves links in a page to the final URLs of other pages and files.
+#[derive(Debug)]
+pub struct Links<'a> {
+    /// The URL path of every page, keyed by the page's path relative to the
+    /// src directory.
+    pub urls: &'a HashMap<PathBuf, String>,
+    /// The path of the page being rendered, relative to the src directory.
+    pub src_path: &'a Path,
+    /// The URL path of the page being rendered.
+    pub url_path: &'a str,
+    /// Whether pretty URLs are used.
+    pub pretty_urls: bool,
+}
+
+impl Links<'_> {
+    /// Resolve a link in the page to the final URL.
+    ///
+    /// Links to other pages are resolved to the page's URL and all relative
+    /// links are adjusted so that they work from where the page is rendered.
+    pub fn resolve<'u>(&self, url: CowStr<'u>) -> CowStr<'u> {
+        if url.contains(':') || url.starts_with('#') || url.starts_with('?') {
+            return url;
+        }
+        let (link, rest) = url.split_at(url.find(&['#', '?'][..]).unwrap_or(url.len()));
+        let (absolute, target) = match link.strip_prefix('/') {
+            Some(link) => (true, util::normalize_path(Path::new(link))),
+            None => (
+                false,
+                util::normalize_path(&self.src_path.parent().unwrap().join(link)),
+            ),
+        };
+        if target
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            return url;
+        }
+        let mut target_url = match self.urls.get(&target) {
+            Some(url) => url.clone(),
+            None => fallback_url(&target, self.pretty_urls),
+        };
+        if link.ends_with('/') && !target_url.is_empty() && !target_url.ends_with('/') {
+            target_url.push('/');
+        }
+        let resolved = if absolute {
+            format!("/{}", target_url)
+        } else {
+            let dir = &self.url_path[..self.url_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+            relative_url(dir, &target_url)
+        };
+        CowStr::from(resolved + rest)
+    }
+}
+
+/// Fix Markdown links by replacing `.md` with `.html`, or by resolving them
+/// using the given `Links`.
+fn fix_markdown_links<'a>(event: Event<'a>, links: Option<&Links>) -> Event<'a> {
+    let fix = |url| match links {
+        Some(links) => links.resolve(url),
+        None => fix_markdown_url(url),
+    };
     match event {
         Event::Start(Tag::Link(link_type, url, title)) => {
-            Event::Start(Tag::Link(link_type, fix_markdown_url(url), title))
+            Event::Start(Tag::Link(link_type, fix(url), title))
         }
         Event::Start(Tag::Image(link_type, url, title)) => {
-            Event::Start(Tag::Image(link_type, fix_markdown_url(url), title))
+            Event::Start(Tag::Image(link_type, fix(url), title))
         }
         _ => event,
     }
 }
 
+/// Escape text for HTML.
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::new();
+    pulldown_cmark::escape::escape_html(&mut escaped, s).unwrap();
+    escaped
+}
+
+/// Remove hidden lines from Rust code, the same way as `rustdoc` and mdBook.
+///
+/// Hidden lines start with `# ` or are just `#`, a line starting with `##` is
+/// shown with a single `#`.
+fn strip_hidden_lines(code: &str) -> String {
+    LinesWithEndings::from(code)
+        .filter_map(|line| {
+            let trimmed = line.trim_start();
+            let indent = &line[..line.len() - trimmed.len()];
+            if let Some(rest) = trimmed.strip_prefix("##") {
+                Some(format!("{}#{}", indent, rest))
+            } else if trimmed.starts_with("# ") || trimmed.trim_end() == "#" {
+                None
+            } else {
+                Some(line.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Highlight a code block, the language is the first word of the fenced code
+/// block's info string.
+///
+/// Code blocks without a language or with an unknown language are not
+/// highlighted.
+fn highlight(kind: &CodeBlockKind, code: &str) -> String {
+    let lang = match kind {
+        CodeBlockKind::Fenced(info) => info.split(&[' ', ','][..]).next().unwrap(),
+        CodeBlockKind::Indented => "",
+    };
+    let stripped;
+    let code = if lang == "rust" {
+        stripped = strip_hidden_lines(code);
+        &stripped
+    } else {
+        code
+    };
+    let syntax = match SYNTAXES.find_syntax_by_token(lang) {
+        Some(syntax) if !lang.is_empty() => syntax,
+        _ => {
+            let class = if lang.is_empty() {
+                String::new()
+            } else {
+                format!(r#" class="language-{}""#, escape_html(lang))
+            };
+            return format!("<pre><code{}>{}</code></pre>\n", class, escape_html(code));
+        }
+    };
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        if generator
+            .parse_html_for_line_which_includes_newline(line)
+            .is_err()
+        {
+            return highlight(&CodeBlockKind::Indented, code);
+        }
+    }
+    format!(
+        "<pre class=\"hl-code\"><code class=\"language-{}\">{}</code></pre>\n",
+        escape_html(lang),
+        generator.finalize()
+    )
+}
+
+/// Generate the CSS stylesheet for the given highlighting theme.
+pub fn highlight_stylesheet(theme: &str) -> Result<String> {
+    let theme = THEMES.themes.get(theme).ok_or_else(|| {
+        let mut names: Vec<_> = THEMES
+            .themes
+            .keys()
+            .map(|name| format!("`{}`", name))
+            .collect();
+        names.sort();
+        anyhow!(
+            "unknown highlight theme `{}`, expected one of {}",
+            theme,
+            names.join(", ")
+        )
+    })?;
+    syntect::html::css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .context("failed to generate highlight stylesheet")
+}
+
+/// Replace code blocks with highlighted HTML.
+struct Highlight<I> {
+    /// The underlying events.
+    events: I,
+}
+
+impl<'a, I> Iterator for Highlight<I>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = match self.events.next()? {
+            Event::Start(Tag::CodeBlock(kind)) => kind,
+            event => return Some(event),
+        };
+        let mut code = String::new();
+        for event in &mut self.events {
+            match event {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(Tag::CodeBlock(_)) => break,
+                _ => {}
+            }
+        }
+        Some(Event::Html(highlight(&kind, &code).into()))
+    }
+}
+
+/// A heading in the table of contents.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Heading {
+    /// The heading level, from 1 to 6.
+    pub level: u32,
+    /// The heading's `id` attribute.
+    pub id: String,
+    /// The heading's text.
+    pub title: String,
+    /// The headings nested under this one.
+    pub children: Vec<Self>,
+}
+
+/// Assigns unique `id`s to headings.
+#[derive(Debug, Default)]
+struct HeadingIds {
+    /// The `id`s that have already been assigned.
+    seen: HashSet<String>,
+}
+
+impl HeadingIds {
+    /// Returns a unique `id` for a heading with the given text.
+    ///
+    /// Duplicate headings are suffixed with a counter, for example the second
+    /// "Examples" heading gets the `id` "examples-1".
+    fn next(&mut self, title: &str) -> String {
+        let slug = util::slugify(title);
+        let slug = if slug.is_empty() {
+            "section".into()
+        } else {
+            slug
+        };
+        let mut id = slug.clone();
+        let mut n = 0;
+        while self.seen.contains(&id) {
+            n += 1;
+            id = format!("{}-{}", slug, n);
+        }
+        self.seen.insert(id.clone());
+        id
+    }
+}
+
+/// Returns the plain text of a heading.
+fn heading_text(events: &[Event]) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Nest a flat list of headings into a tree, each heading contains the
+/// subsequent headings with a greater level.
+fn nest_headings(headings: Vec<Heading>) -> Vec<Heading> {
+    let mut stack: Vec<Heading> = Vec::new();
+    let mut tree = Vec::new();
+    for heading in headings {
+        while stack.last().is_some_and(|h| h.level >= heading.level) {
+            let child = stack.pop().unwrap();
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(child),
+                None => tree.push(child),
+            }
+        }
+        stack.push(heading);
+    }
+    while let Some(child) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(child),
+            None => tree.push(child),
+        }
+    }
+    tree
+}
+
+/// Render a table of contents as an HTML list.
+pub fn toc_html(headings: &[Heading]) -> String {
+    fn push_list(html: &mut String, headings: &[Heading]) {
+        html.push_str("<ul>\n");
+        for heading in headings {
+            html.push_str(&format!(
+                r##"<li><a href="#{}">{}</a>"##,
+                heading.id,
+                escape_html(&heading.title)
+            ));
+            if !heading.children.is_empty() {
+                html.push('\n');
+                push_list(html, &heading.children);
+            }
+            html.push_str("</li>\n");
+        }
+        html.push_str("</ul>\n");
+    }
+
+    let mut html = String::from("<nav class=\"toc\">\n");
+    push_list(&mut html, headings);
+    html.push_str("</nav>\n");
+    html
+}
+
+/// Add `id`s to headings.
+struct Anchors<'a, I> {
+    /// The underlying events.
+    events: I,
+    /// Assigns the `id`s.
+    ids: HeadingIds,
+    /// Events that have been processed but not yet returned.
+    buffer: std::vec::IntoIter<Event<'a>>,
+}
+
+impl<'a, I> Iterator for Anchors<'a, I>
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(event) = self.buffer.next() {
+            return Some(event);
+        }
+        let level = match self.events.next()? {
+            Event::Start(Tag::Heading(level)) => level,
+            event => return Some(event),
+        };
+        let mut inner = Vec::new();
+        for event in &mut self.events {
+            match event {
+                Event::End(Tag::Heading(_)) => break,
+                event => inner.push(event),
+            }
+        }
+        let id = self.ids.next(&heading_text(&inner));
+        inner.push(Event::Html(format!("</h{}>\n", level).into()));
+        self.buffer = inner.into_iter();
+        Some(Event::Html(format!(r#"<h{} id="{}">"#, level, id).into()))
+    }
+}
+
 /// A Markdown to HTML renderer.
 pub struct Renderer<'s> {
+    /// The Markdown source.
+    source: &'s str,
     /// The raw parser.
     parser: Parser<'s>,
+    /// Resolves links to other pages and files.
+    links: Option<&'s Links<'s>>,
 }
 
 impl<'s> Renderer<'s> {
     /// Create a new `Renderer`.
     pub fn new(s: &'s str) -> Self {
         let parser = Parser::new_ext(s, Options::all());
-        Self { parser }
+        Self {
+            source: s,
+            parser,
+            links: None,
+        }
+    }
+
+    /// Resolve links to other pages and files using the given `Links`,
+    /// instead of only replacing `.md` with `.html`.
+    pub fn resolve_links(mut self, links: &'s Links<'s>) -> Self {
+        self.links = Some(links);
+        self
+    }
+
+    /// Consume the `Renderer` and return the destination of every link and
+    /// image, as written in the source, along with the line it is on.
+    pub fn links(self) -> Vec<(String, usize)> {
+        let source = self.source;
+        self.parser
+            .into_offset_iter()
+            .filter_map(|(event, range)| match event {
+                Event::Start(Tag::Link(_, url, _)) | Event::Start(Tag::Image(_, url, _)) => {
+                    let line = source[..range.start].matches('\n').count() + 1;
+                    Some((url.into_string(), line))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Consume the `Renderer` and return the table of contents.
+    pub fn toc(self) -> Vec<Heading> {
+        let mut ids = HeadingIds::default();
+        let mut headings = Vec::new();
+        let mut events = self.parser;
+        while let Some(event) = events.next() {
+            if let Event::Start(Tag::Heading(level)) = event {
+                let inner: Vec<_> = (&mut events)
+                    .take_while(|event| !matches!(event, Event::End(Tag::Heading(_))))
+                    .collect();
+                let title = heading_text(&inner);
+                headings.push(Heading {
+                    level,
+                    id: ids.next(&title),
+                    title,
+                    children: Vec::new(),
+                });
+            }
+        }
+        nest_headings(headings)
     }
 
     /// Consume the `Renderer` and output HTML.
     pub fn render(self) -> String {
+        let links = self.links;
         let mut result = String::new();
-        let events = self.parser.map(fix_markdown_links);
+        let events = Highlight {
+            events: Anchors {
+                events: self.parser.map(|event| fix_markdown_links(event, links)),
+                ids: HeadingIds::default(),
+                buffer: Vec::new().into_iter(),
+            },
+        };
         html::push_html(&mut result, events);
         result
     }
 }
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renderer_render_highlights_code_blocks() {
+        let html = Renderer::new("```rust\nfn main() {}\n```\n").render();
+        assert!(html.starts_with(r#"<pre class="hl-code"><code class="language-rust">"#));
+        assert!(html.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#));
+        assert!(html.ends_with("</code></pre>\n"));
+    }
+
+    #[test]
+    fn strip_hidden_lines_basic() {
+        assert_eq!(
+            strip_hidden_lines(
+                "# use std::io;\n#\nfn main() {\n    # let x = 1;\n    ##[a]\n}\n#[test]\n"
+            ),
+            "fn main() {\n    #[a]\n}\n#[test]\n"
+        );
+    }
+
+    #[test]
+    fn renderer_render_hides_rust_lines() {
+        let html = Renderer::new("```rust\n# fn hidden() {}\nfn main() {}\n```\n").render();
+        assert!(!html.contains("hidden"));
+        let html = Renderer::new("```python\n# comment\n```\n").render();
+        assert!(html.contains("comment"));
+    }
+
+    #[test]
+    fn renderer_render_unknown_language() {
+        let html = Renderer::new("```nope\n<a>\n```\n\n    <b>\n").render();
+        assert_eq!(
+            html,
+            "<pre><code class=\"language-nope\">&lt;a&gt;\n</code></pre>\n\
+             <pre><code>&lt;b&gt;\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn renderer_render_heading_ids() {
+        let html = Renderer::new("# Hello *World*\n\n## Hello World\n\n## `a` & b\n").render();
+        assert_eq!(
+            html,
+            "<h1 id=\"hello-world\">Hello <em>World</em></h1>\n\
+             <h2 id=\"hello-world-1\">Hello World</h2>\n\
+             <h2 id=\"a-b\"><code>a</code> &amp; b</h2>\n"
+        );
+    }
+
+    #[test]
+    fn renderer_toc() {
+        let toc = Renderer::new("## A\n\n### B\n\n# C\n\n### D\n\n## E\n").toc();
+        let heading = |level, title: &str, children| Heading {
+            level,
+            id: title.to_lowercase(),
+            title: title.into(),
+            children,
+        };
+        assert_eq!(
+            toc,
+            vec![
+                heading(2, "A", vec![heading(3, "B", vec![])]),
+                heading(
+                    1,
+                    "C",
+                    vec![heading(3, "D", vec![]), heading(2, "E", vec![])]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn renderer_links() {
+        let text = "# Hello\n\nSee [a](a.md#x).\n\n![image](images/b.png)\n";
+        assert_eq!(
+            Renderer::new(text).links(),
+            vec![("a.md#x".to_string(), 3), ("images/b.png".to_string(), 5)]
+        );
+    }
+
+    #[test]
+    fn relative_url_basic() {
+        assert_eq!(relative_url("", "a.html"), "a.html");
+        assert_eq!(relative_url("", ""), "./");
+        assert_eq!(relative_url("a/", "a/c.html"), "c.html");
+        assert_eq!(relative_url("a/b/", "p1/"), "../../p1/");
+        assert_eq!(relative_url("a/b/", ""), "../../");
+        assert_eq!(relative_url("a/b/", "a/b/"), "./");
+    }
+
+    #[test]
+    fn absolute_url_basic() {
+        let base_url = "https://example.com/";
+        let absolute = |url| absolute_url(base_url, "posts/a/", url);
+        assert_eq!(absolute("b.png"), "https://example.com/posts/a/b.png");
+        assert_eq!(
+            absolute("../b.html#x"),
+            "https://example.com/posts/b.html#x"
+        );
+        assert_eq!(absolute("../../"), "https://example.com/");
+        assert_eq!(absolute(".."), "https://example.com/posts/");
+        assert_eq!(absolute("#x"), "https://example.com/posts/a/#x");
+        assert_eq!(absolute("/about/"), "https://example.com/about/");
+        assert_eq!(absolute("https://example.org/"), "https://example.org/");
+        assert_eq!(absolute("mailto:me@example.com"), "mailto:me@example.com");
+        assert_eq!(
+            absolute_url(base_url, "posts/a.html", "b.html?x=1"),
+            "https://example.com/posts/b.html?x=1"
+        );
+    }
+
+    #[test]
+    fn absolute_links_basic() {
+        assert_eq!(
+            absolute_links(
+                "<p><a href=\"../b/\">b</a> <img src=\"c.png\" alt=\"c\"></p>",
+                "https://example.com/",
+                "posts/a/"
+            ),
+            "<p><a href=\"https://example.com/posts/b/\">b</a> \
+             <img src=\"https://example.com/posts/a/c.png\" alt=\"c\"></p>"
+        );
+    }
+
+    #[test]
+    fn links_resolve() {
+        let mut urls = HashMap::new();
+        urls.insert(PathBuf::from("about.md"), "about/".to_string());
+        urls.insert(["posts", "a.md"].iter().collect(), "2020/01/a/".to_string());
+        let links = Links {
+            urls: &urls,
+            src_path: &["posts", "b.md"].iter().collect::<PathBuf>(),
+            url_path: "2020/02/b/",
+            pretty_urls: true,
+        };
+        let resolve = |url| links.resolve(CowStr::from(url)).into_string();
+        assert_eq!(resolve("a.md#intro"), "../../01/a/#intro");
+        assert_eq!(resolve("../about.md"), "../../../about/");
+        assert_eq!(resolve("/about.md"), "/about/");
+        assert_eq!(resolve("missing.md"), "../../../posts/missing/");
+        assert_eq!(resolve("reindex.md"), "../../../posts/reindex/");
+        assert_eq!(resolve("_index.md"), "../../../posts/");
+        assert_eq!(resolve("image.png"), "../../../posts/image.png");
+        assert_eq!(resolve("../../outside.md"), "../../outside.md");
+        assert_eq!(resolve("#heading"), "#heading");
+        assert_eq!(
+            resolve("https://example.com/a.md"),
+            "https://example.com/a.md"
+        );
+    }
+
+    #[test]
+    fn highlight_stylesheet_unknown_theme() {
+        assert!(highlight_stylesheet("base16-ocean.dark").is_ok());
+        assert!(highlight_stylesheet("nope")
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown highlight theme `nope`"));
+    }
+}
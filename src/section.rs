@@ -0,0 +1,219 @@
+//! Group pages into sections using the directory structure of the project.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use serde_json as json;
+use serde_json::json;
+
+use crate::app::Page;
+use crate::output;
+use crate::paginate::{self, paginate};
+use crate::prelude::*;
+use crate::util;
+
+/////////////////////////////////////////////////////////////////////////
+// Section definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// A directory of pages.
+#[derive(Debug, Default)]
+struct Section {
+    /// The rendering context of the section's `_index.md` page.
+    index: Option<json::Value>,
+    /// The rendering context of each page directly in this section.
+    pages: Vec<json::Value>,
+}
+
+/// Every section in the project, keyed by directory relative to the src
+/// directory. The root section has an empty path.
+#[derive(Debug)]
+pub struct Sections {
+    sections: BTreeMap<PathBuf, Section>,
+    /// Whether to use pretty URLs.
+    pretty_urls: bool,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Section implementations
+/////////////////////////////////////////////////////////////////////////
+
+impl Page {
+    /// Whether this page is a section's `_index.md` page.
+    pub fn is_section_index(&self) -> bool {
+        self.path
+            .file_name()
+            .map(|s| s == "_index.md")
+            .unwrap_or(false)
+    }
+}
+
+/// Get the location of a section's listing page relative to the output
+/// directory.
+fn section_path(dir: &Path) -> String {
+    dir.components()
+        .map(|c| format!("{}/", c.as_os_str().to_string_lossy()))
+        .chain(Some("index.html".to_string()))
+        .collect()
+}
+
+impl Sections {
+    /// Create a new `Sections` containing only the root section.
+    pub fn new(pretty_urls: bool) -> Self {
+        let mut sections = BTreeMap::new();
+        sections.insert(PathBuf::new(), Section::default());
+        Self {
+            sections,
+            pretty_urls,
+        }
+    }
+
+    /// Get the section for the given directory, creating it and all of its
+    /// parent sections if they don't exist.
+    fn section_mut(&mut self, dir: &Path) -> &mut Section {
+        for ancestor in dir.ancestors() {
+            self.sections.entry(ancestor.to_path_buf()).or_default();
+        }
+        self.sections.get_mut(dir).unwrap()
+    }
+
+    /// Add a page with the given rendering context to its section.
+    ///
+    /// If the page is a section's `_index.md` page then it is used as the
+    /// section's front matter and content instead.
+    pub fn insert(&mut self, page: &Page, ctx: &json::Value) {
+        let section = self.section_mut(page.path.parent().unwrap());
+        if page.is_section_index() {
+            section.index = Some(ctx.clone());
+        } else {
+            section.pages.push(ctx.clone());
+        }
+    }
+
+    /// Rendering context for the section with the given directory, including
+    /// all of its child sections.
+    fn context(&self, dir: &Path) -> json::Value {
+        let section = &self.sections[dir];
+        let mut pages = section.pages.clone();
+        paginate::sort_newest_first(&mut pages);
+        let sections: Vec<_> = self
+            .sections
+            .keys()
+            .filter(|child| child.parent() == Some(dir))
+            .map(|child| self.context(child))
+            .collect();
+        let index = section.index.as_ref();
+        json!({
+            "name": dir.file_name().map(|s| s.to_string_lossy()),
+            "path": util::url_path(&section_path(dir), self.pretty_urls),
+            "meta": index.map(|index| &index["meta"]),
+            "content": index.map(|index| &index["content"]),
+            "pages": pages,
+            "sections": sections,
+        })
+    }
+
+    /// Rendering context for the root section.
+    pub fn root_context(&self) -> json::Value {
+        self.context(Path::new(""))
+    }
+
+    /// Render a listing page for each section, except the root section.
+    ///
+    /// `page_paths` are the output paths of every page, a section's listing is
+    /// not rendered if a page is rendered to the same path, for example
+    /// `docs/index.md`.
+    pub fn render(
+        &self,
+        templates: &tera::Tera,
+        base_ctx: &tera::Context,
+        page_paths: &HashMap<PathBuf, PathBuf>,
+        output: &mut output::Output,
+    ) -> Result<()> {
+        let paginate_by = output.config().paginate_by();
+        for dir in self.sections.keys().filter(|dir| dir.parent().is_some()) {
+            let path = section_path(dir);
+            if page_paths.contains_key(Path::new(&path)) {
+                continue;
+            }
+            let section = self.context(dir);
+            let mut ctx = base_ctx.clone();
+            ctx.insert("section", &section);
+            let pages = section["pages"].as_array().unwrap();
+            for pager in paginate(pages, paginate_by, &path, self.pretty_urls) {
+                ctx.insert("path_to_root", &pager.path_to_root);
+                ctx.insert("paginator", &pager.context);
+                let rendered = templates
+                    .render("section.html", &ctx)
+                    .with_context(|| format!("failed to render section `{}`", dir.display()))?;
+                output.push_file(output::File::new(pager.path, rendered));
+            }
+        }
+        Ok(())
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(path: &str) -> Page {
+        Page {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn section_path_basic() {
+        assert_eq!(section_path(Path::new("")), "index.html");
+        assert_eq!(section_path(Path::new("posts")), "posts/index.html");
+        assert_eq!(
+            section_path(&["posts", "2020"].iter().collect::<PathBuf>()),
+            "posts/2020/index.html"
+        );
+    }
+
+    #[test]
+    fn sections_root_context() {
+        let mut sections = Sections::new(false);
+        sections.insert(&page("about.md"), &json!({ "path": "about.html" }));
+        sections.insert(
+            &page("posts/2020/a.md"),
+            &json!({ "path": "posts/2020/a.html" }),
+        );
+        sections.insert(
+            &page("posts/_index.md"),
+            &json!({ "meta": { "title": "Posts" }, "content": "<p>Intro</p>" }),
+        );
+        assert_eq!(
+            sections.root_context(),
+            json!({
+                "name": null,
+                "path": "index.html",
+                "meta": null,
+                "content": null,
+                "pages": [{ "path": "about.html" }],
+                "sections": [{
+                    "name": "posts",
+                    "path": "posts/index.html",
+                    "meta": { "title": "Posts" },
+                    "content": "<p>Intro</p>",
+                    "pages": [],
+                    "sections": [{
+                        "name": "2020",
+                        "path": "posts/2020/index.html",
+                        "meta": null,
+                        "content": null,
+                        "pages": [{ "path": "posts/2020/a.html" }],
+                        "sections": [],
+                    }],
+                }],
+            })
+        );
+    }
+}
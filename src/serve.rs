@@ -0,0 +1,218 @@
+//! Serve a `Project` over HTTP and reload it when it changes.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use tiny_http::{Header, Request, Response};
+
+use crate::app::Incremental;
+use crate::config::Config;
+use crate::prelude::*;
+use crate::preprocess::Directives;
+use crate::util;
+use crate::watch;
+
+/// The URL path that serves the current build version.
+const VERSION_PATH: &str = "/__belong/version";
+
+/// Script injected into served HTML pages that polls the build version and
+/// reloads the page whenever it changes.
+const LIVE_RELOAD: &str = r#"<script>
+  (function () {
+    var version = "{{ version }}";
+    setInterval(function () {
+      fetch("/__belong/version")
+        .then(function (response) { return response.text(); })
+        .then(function (latest) { if (latest !== version) { location.reload(); } })
+        .catch(function () {});
+    }, 1000);
+  })();
+</script>
+"#;
+
+/////////////////////////////////////////////////////////////////////////
+// Server definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// State shared between the file watcher and the HTTP server.
+#[derive(Debug)]
+struct State {
+    /// The directory to serve files from.
+    output_dir: PathBuf,
+    /// Incremented every time the project is rebuilt.
+    version: AtomicUsize,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Server implementations
+/////////////////////////////////////////////////////////////////////////
+
+/// Resolve a URL path to a file in the output directory.
+fn resolve(output_dir: &Path, url: &str) -> Option<PathBuf> {
+    let mut path = output_dir.to_path_buf();
+    for segment in url.split('/').filter(|s| !s.is_empty()) {
+        match util::percent_decode(segment)?.as_str() {
+            "." | ".." => return None,
+            segment if segment.contains('\\') => return None,
+            segment => path.push(segment),
+        }
+    }
+    if path.is_dir() {
+        path.push("index.html");
+    }
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Guess the content type of a file from its extension.
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "application/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Insert the live reload script at the end of an HTML document.
+fn inject_live_reload(html: &str, version: usize) -> String {
+    let script = LIVE_RELOAD.replace("{{ version }}", &version.to_string());
+    let index = html.rfind("</body>").unwrap_or(html.len());
+    let mut result = String::with_capacity(html.len() + script.len());
+    result.push_str(&html[..index]);
+    result.push_str(&script);
+    result.push_str(&html[index..]);
+    result
+}
+
+impl State {
+    /// Build the response for the given URL.
+    fn response(&self, url: &str) -> Result<Response<std::io::Cursor<Vec<u8>>>> {
+        let url = url.split(&['?', '#'][..]).next().unwrap();
+        let version = self.version.load(Ordering::SeqCst);
+
+        if url == VERSION_PATH {
+            return Ok(Response::from_string(version.to_string()));
+        }
+
+        let path = match resolve(&self.output_dir, url) {
+            Some(path) => path,
+            None => return Ok(Response::from_string("404 Not Found").with_status_code(404)),
+        };
+        let mut contents =
+            fs::read(&path).with_context(|| format!("failed to read `{}`", path.display()))?;
+        if path.extension().map(|s| s == "html").unwrap_or(false) {
+            contents = inject_live_reload(&String::from_utf8_lossy(&contents), version).into();
+        }
+        let header = Header::from_bytes(&b"Content-Type"[..], content_type(&path)).unwrap();
+        Ok(Response::from_data(contents).with_header(header))
+    }
+
+    /// Respond to a single HTTP request.
+    fn handle(&self, request: Request) {
+        let response = match self.response(request.url()) {
+            Ok(response) => response,
+            Err(err) => {
+                log::error!("{:?}", err);
+                Response::from_string("500 Internal Server Error").with_status_code(500)
+            }
+        };
+        if let Err(err) = request.respond(response) {
+            log::warn!("failed to send response: {}", err);
+        }
+    }
+}
+
+/// Build the project, serve it on the given port, and rebuild it whenever it
+/// changes.
+pub fn serve(config: Config, port: u16, open: bool) -> Result<()> {
+    let state = Arc::new(State {
+        output_dir: config.output_dir(),
+        version: AtomicUsize::new(0),
+    });
+
+    let mut build = Incremental::new(config, Directives::default());
+    if let Err(err) = build.build() {
+        log::error!("{:?}", err);
+    }
+
+    let server = tiny_http::Server::http(("127.0.0.1", port))
+        .map_err(|err| anyhow!("{}", err))
+        .with_context(|| format!("failed to listen on port {}", port))?;
+    let url = format!("http://127.0.0.1:{}", port);
+    log::info!("serving project on {}", url);
+
+    let server_state = state.clone();
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            server_state.handle(request);
+        }
+    });
+
+    if open {
+        open::that(&url).context("failed to open web page in browser")?;
+    }
+
+    watch::rebuild_on_change(&mut build, |_| {
+        state.version.fetch_add(1, Ordering::SeqCst);
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_rejects_parent_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert_eq!(resolve(temp_dir.path(), "/../etc/passwd"), None);
+        assert_eq!(resolve(temp_dir.path(), "/%2e%2e/etc/passwd"), None);
+    }
+
+    #[test]
+    fn resolve_directory_index() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_dir = temp_dir.path();
+        fs::create_dir(output_dir.join("posts")).unwrap();
+        fs::write(output_dir.join("index.html"), "").unwrap();
+        fs::write(output_dir.join("posts").join("index.html"), "").unwrap();
+        assert_eq!(
+            resolve(output_dir, "/").unwrap(),
+            output_dir.join("index.html")
+        );
+        assert_eq!(
+            resolve(output_dir, "/posts/").unwrap(),
+            output_dir.join("posts").join("index.html")
+        );
+        assert_eq!(resolve(output_dir, "/missing.html"), None);
+    }
+
+    #[test]
+    fn inject_live_reload_before_body() {
+        let html = inject_live_reload("<html><body></body></html>", 3);
+        assert!(html.starts_with("<html><body><script>"));
+        assert!(html.contains(r#"var version = "3";"#));
+        assert!(html.ends_with("</script>\n</body></html>"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Group pages using taxonomies, for example tags and categories.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde_json as json;
+use serde_json::json;
+
+use crate::app::Page;
+use crate::output;
+use crate::paginate::{self, paginate};
+use crate::prelude::*;
+use crate::util;
+
+/////////////////////////////////////////////////////////////////////////
+// Taxonomy definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// A single term in a taxonomy, for example a tag.
+#[derive(Debug)]
+struct Term {
+    /// The name of the term, as first seen in a page's front matter.
+    name: String,
+    /// The rendering context of each page with this term.
+    pages: Vec<json::Value>,
+}
+
+/// A taxonomy and each of its terms.
+#[derive(Debug)]
+pub struct Taxonomy {
+    /// The name of the taxonomy, for example "tags".
+    name: String,
+    /// Each of the terms, keyed by slug.
+    terms: BTreeMap<String, Term>,
+    /// Whether to use pretty URLs.
+    pretty_urls: bool,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Taxonomy implementations
+/////////////////////////////////////////////////////////////////////////
+
+impl Taxonomy {
+    /// Create a new empty `Taxonomy`.
+    pub fn new(name: &str, pretty_urls: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            terms: BTreeMap::new(),
+            pretty_urls,
+        }
+    }
+
+    /// The directory the taxonomy is rendered to.
+    fn dir(&self) -> String {
+        util::slugify(&self.name)
+    }
+
+    /// The location of the given term's listing page relative to the output
+    /// directory.
+    fn term_path(&self, slug: &str) -> String {
+        if self.pretty_urls {
+            format!("{}/{}/index.html", self.dir(), slug)
+        } else {
+            format!("{}/{}.html", self.dir(), slug)
+        }
+    }
+
+    /// The URL path of the given term's listing page.
+    fn term_url(&self, slug: &str) -> String {
+        util::url_path(&self.term_path(slug), self.pretty_urls)
+    }
+
+    /// Rendering context for this taxonomy's terms in the given page.
+    pub fn page_context(&self, page: &Page) -> json::Value {
+        page.front_matter
+            .terms(&self.name)
+            .into_iter()
+            .map(|name| {
+                let slug = util::slugify(name);
+                json!({
+                    "name": name,
+                    "slug": slug,
+                    "path": self.term_url(&slug),
+                })
+            })
+            .collect()
+    }
+
+    /// Add a page with the given rendering context to each of its terms.
+    pub fn insert(&mut self, page: &Page, ctx: &json::Value) {
+        for name in page.front_matter.terms(&self.name) {
+            self.terms
+                .entry(util::slugify(name))
+                .or_insert_with(|| Term {
+                    name: name.to_string(),
+                    pages: Vec::new(),
+                })
+                .pages
+                .push(ctx.clone());
+        }
+    }
+
+    /// Rendering context for a term, only pages with a title are included.
+    fn term_context(&self, slug: &str, term: &Term) -> json::Value {
+        let mut pages: Vec<_> = term
+            .pages
+            .iter()
+            .filter(|page| !page["meta"]["title"].is_null())
+            .cloned()
+            .collect();
+        paginate::sort_newest_first(&mut pages);
+        json!({
+            "name": term.name,
+            "slug": slug,
+            "path": self.term_url(slug),
+            "pages": pages,
+        })
+    }
+
+    /// Render the taxonomy's index page and a listing page for each term.
+    ///
+    /// Nothing is rendered if no pages use this taxonomy.
+    pub fn render(
+        &self,
+        templates: &tera::Tera,
+        base_ctx: &tera::Context,
+        output: &mut output::Output,
+    ) -> Result<()> {
+        if self.terms.is_empty() {
+            return Ok(());
+        }
+
+        let terms: Vec<_> = self
+            .terms
+            .iter()
+            .map(|(slug, term)| self.term_context(slug, term))
+            .collect();
+
+        let mut ctx = base_ctx.clone();
+        ctx.insert("path_to_root", "../");
+        ctx.insert(
+            "taxonomy",
+            &json!({
+                "name": self.name,
+                "slug": self.dir(),
+                "path": util::url_path(&format!("{}/index.html", self.dir()), self.pretty_urls),
+                "terms": terms,
+            }),
+        );
+        let rendered = templates
+            .render("taxonomy.html", &ctx)
+            .with_context(|| format!("failed to render taxonomy `{}`", self.name))?;
+        let path = PathBuf::from(self.dir()).join("index.html");
+        output.push_file(output::File::new(path, rendered));
+
+        let paginate_by = output.config().paginate_by();
+        for term in terms {
+            let name = term["name"].as_str().unwrap();
+            let path = self.term_path(term["slug"].as_str().unwrap());
+            let pages = term["pages"].as_array().unwrap();
+            ctx.insert("term", &term);
+            for pager in paginate(pages, paginate_by, &path, self.pretty_urls) {
+                ctx.insert("path_to_root", &pager.path_to_root);
+                ctx.insert("paginator", &pager.context);
+                let rendered = templates.render("term.html", &ctx).with_context(|| {
+                    format!(
+                        "failed to render term `{}` in taxonomy `{}`",
+                        name, self.name
+                    )
+                })?;
+                output.push_file(output::File::new(pager.path, rendered));
+            }
+        }
+
+        Ok(())
+    }
+}
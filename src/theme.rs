@@ -1,25 +1,40 @@
 //! Defines how we render a `Project`.
 
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs;
 use std::path;
 use std::path::{Path, PathBuf};
 
+use regex_macro::regex;
 use serde_json as json;
 use serde_json::json;
 
 use crate::app::Page;
 use crate::config::Config;
 use crate::output;
+use crate::paginate::{self, paginate};
 use crate::prelude::*;
-use crate::renderer::Renderer;
+use crate::renderer::{self, Renderer};
+use crate::section::Sections;
+use crate::taxonomy::Taxonomy;
+use crate::util;
 
 /// Namespaced predefined templates.
 mod template {
     pub const BASE: &str = include_str!("theme/templates/base.html");
     pub const INDEX: &str = include_str!("theme/templates/index.html");
+    pub const MACROS: &str = include_str!("theme/templates/macros.html");
     pub const PAGE: &str = include_str!("theme/templates/page.html");
+    pub const PAGINATION: &str = include_str!("theme/templates/pagination.html");
+    pub const RSS: &str = include_str!("theme/templates/rss.xml");
+    pub const ATOM: &str = include_str!("theme/templates/atom.xml");
+    pub const SITEMAP: &str = include_str!("theme/templates/sitemap.xml");
+    pub const ROBOTS: &str = include_str!("theme/templates/robots.txt");
+    pub const SECTION: &str = include_str!("theme/templates/section.html");
+    pub const TAXONOMY: &str = include_str!("theme/templates/taxonomy.html");
+    pub const TERM: &str = include_str!("theme/templates/term.html");
 }
 
 /// Namespaced predefined stylesheets.
@@ -59,6 +74,9 @@ pub struct Theme {
     templates: Vec<Template>,
     /// Each of the theme's stylesheets.
     stylesheets: Vec<Stylesheet>,
+    /// Every file in the theme's `static` directory, these are copied to the
+    /// output directory as is.
+    static_files: Vec<output::File>,
 }
 
 /////////////////////////////////////////////////////////////////////////
@@ -73,7 +91,12 @@ impl From<Stylesheet> for output::File {
 
 impl From<File> for Template {
     fn from((path, contents): File) -> Self {
-        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        // The name is the path relative to the templates directory, always
+        // using forward slashes, for example `partials/header.html`.
+        let components: Vec<_> = path.components().skip(1).map(|c| c.as_os_str()).collect();
+        let name = Join::join(components.as_slice(), "/")
+            .to_string_lossy()
+            .into_owned();
         Self { name, contents }
     }
 }
@@ -85,22 +108,121 @@ impl From<File> for Stylesheet {
 }
 
 impl Page {
+    /// Get the location of the rendered page relative to the output directory.
+    ///
+    /// A section's `_index.md` page is rendered as the section's listing page.
+    /// If there is a permalink pattern for the page's kind then it is used,
+    /// otherwise the page is rendered next to its source using its slug. With
+    /// pretty URLs every page is rendered to its own directory, for example
+    /// `posts/hello.md` is rendered to `posts/hello/index.html`.
+    pub fn output_path(&self, config: &Config) -> Result<PathBuf> {
+        if self.is_section_index() {
+            return Ok(self.path.with_file_name("index.html"));
+        }
+        let pretty_urls = config.pretty_urls();
+        if let Some(pattern) = self.front_matter.kind().and_then(|k| config.permalink(k)) {
+            let expanded = self
+                .expand_permalink(pattern)
+                .with_context(|| format!("failed to expand permalink pattern `{}`", pattern))?;
+            let path: PathBuf = expanded.split('/').filter(|s| !s.is_empty()).collect();
+            if !util::is_normal_path(&path) {
+                return Err(anyhow!(
+                    "permalink `{}` must not contain `.` or `..` components",
+                    expanded
+                ));
+            }
+            return Ok(if expanded.ends_with('/') || path == Path::new("") {
+                path.join("index.html")
+            } else if path.extension().is_some() {
+                path
+            } else if pretty_urls {
+                path.join("index.html")
+            } else {
+                path.with_extension("html")
+            });
+        }
+        let slug = self.slug();
+        if slug.is_empty() || !util::is_normal_path(Path::new(&slug)) {
+            return Err(anyhow!(
+                "slug `{}` must be a relative path without `.` or `..` components",
+                slug
+            ));
+        }
+        Ok(if pretty_urls && slug != "index" {
+            self.path.with_file_name(slug).join("index.html")
+        } else {
+            self.path.with_file_name(format!("{}.html", slug))
+        })
+    }
+
+    /// The last part of this page's URL, either from the front matter or the
+    /// file name.
+    fn slug(&self) -> String {
+        match self.front_matter.slug() {
+            Some(slug) => slug.to_string(),
+            None => self
+                .path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// Replace the placeholders in a permalink pattern with this page's
+    /// values.
+    fn expand_permalink(&self, pattern: &str) -> Result<String> {
+        let re = regex!(r":(?P<name>[a-z]+)");
+        let mut result = String::new();
+        let mut last = 0;
+        for captures in re.captures_iter(pattern) {
+            let m = captures.get(0).unwrap();
+            let name = &captures["name"];
+            let date = || {
+                self.front_matter
+                    .date()
+                    .ok_or_else(|| anyhow!("`:{}` requires the page to have a date", name))
+            };
+            let value = match name {
+                "year" => date()?.format("%Y").to_string(),
+                "month" => date()?.format("%m").to_string(),
+                "day" => date()?.format("%d").to_string(),
+                "slug" => self.slug(),
+                "section" => {
+                    let components: Vec<_> = self
+                        .path
+                        .parent()
+                        .unwrap()
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect();
+                    components.join("/")
+                }
+                name => return Err(anyhow!("unknown placeholder `:{}`", name)),
+            };
+            result.push_str(&pattern[last..m.start()]);
+            result.push_str(&value);
+            last = m.end();
+        }
+        result.push_str(&pattern[last..]);
+        Ok(result)
+    }
+
     /// Get the URL path for this page, relative to the root of the project.
-    fn url_path(&self) -> Result<String> {
-        self.path
-            .with_extension("html")
-            .components()
-            .map(|c| c.as_os_str())
-            .collect::<Vec<_>>()
-            .join("/")
+    pub fn url_path(&self, config: &Config) -> Result<String> {
+        let path = self.output_path(config)?;
+        let components: Vec<_> = path.components().map(|c| c.as_os_str()).collect();
+        let path = Join::join(components.as_slice(), "/")
             .into_string()
-            .map_err(|_| anyhow!("page path (and subsequently the URL) is not valid UTF-8"))
+            .map_err(|_| anyhow!("page path (and subsequently the URL) is not valid UTF-8"))?;
+        Ok(util::url_path(&path, config.pretty_urls()))
     }
 
     /// Naïve way of determining the path to the root of the project. This only
-    /// works because `self.path()` is relative to the root of the project.
-    fn url_path_to_root(&self) -> Result<String> {
-        self.path
+    /// works because `self.output_path()` is relative to the root of the
+    /// project.
+    fn url_path_to_root(&self, config: &Config) -> Result<String> {
+        self.output_path(config)?
             .parent()
             .unwrap()
             .components()
@@ -115,17 +237,141 @@ impl Page {
             .map_err(|_| anyhow!("page path (and subsequently the URL) is not valid UTF-8"))
     }
 
+    /// Resolves links in this page to other pages and files.
+    fn links<'a>(
+        &'a self,
+        config: &Config,
+        urls: &'a HashMap<PathBuf, String>,
+        url_path: &'a str,
+    ) -> renderer::Links<'a> {
+        renderer::Links {
+            urls,
+            src_path: &self.path,
+            url_path,
+            pretty_urls: config.pretty_urls(),
+        }
+    }
+
     /// Rendering context for a `Page`.
-    fn context(&self) -> Result<json::Value> {
+    ///
+    /// The `urls` are the URL paths of every page keyed by the page's path
+    /// relative to the src directory, these are used to resolve links.
+    fn context(&self, config: &Config, urls: &HashMap<PathBuf, String>) -> Result<json::Value> {
+        let url_path = self.url_path(config)?;
+        let links = self.links(config, urls, &url_path);
         Ok(json!({
             "meta": self.front_matter,
-            "path": self.url_path()?,
-            "content": Renderer::new(&self.contents).render()
+            "path": url_path,
+            "content": Renderer::new(&self.contents).resolve_links(&links).render(),
+            "toc": Renderer::new(&self.contents).toc(),
         }))
     }
+
+    /// The page that the rendered file was generated from, used to report
+    /// problems with links.
+    fn source(&self, config: &Config, urls: &HashMap<PathBuf, String>) -> Result<output::Source> {
+        let url_path = self.url_path(config)?;
+        let links = self.links(config, urls, &url_path);
+        Ok(output::Source {
+            path: self.path.clone(),
+            links: self
+                .links
+                .iter()
+                .map(|(url, line)| output::Link {
+                    url: url.clone(),
+                    resolved: links.resolve(url.as_str().into()).into_string(),
+                    line: *line,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Returns the posts in the given page contexts, newest first.
+///
+/// Posts are pages with a title, a date, and `kind = "post"`.
+fn posts(pages: &[json::Value]) -> Vec<json::Value> {
+    let mut posts: Vec<_> = pages
+        .iter()
+        .filter(|page| {
+            let meta = &page["meta"];
+            !meta["title"].is_null() && !meta["date"].is_null() && meta["kind"] == "post"
+        })
+        .cloned()
+        .collect();
+    paginate::sort_newest_first(&mut posts);
+    posts
+}
+
+/// Check that each generated file, for example a listing, feed, or static
+/// file, is rendered to a different path than every page and every other
+/// generated file.
+fn check_output_paths(
+    page_paths: &HashMap<PathBuf, PathBuf>,
+    generated: &[output::File],
+) -> Result<()> {
+    let mut paths = HashSet::new();
+    for file in generated {
+        let path = file.path();
+        if let Some(page) = page_paths.get(path) {
+            return Err(anyhow!(
+                "page `{}` and a generated file are both rendered to `{}`",
+                page.display(),
+                path.display()
+            ));
+        }
+        if !paths.insert(path) {
+            return Err(anyhow!("`{}` is generated more than once", path.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of the template to render the given page with.
+///
+/// This is the template set in the page's front matter, otherwise a template
+/// named after the page's kind if it exists, otherwise `page.html`.
+fn page_template(templates: &tera::Tera, page: &Page) -> String {
+    if let Some(template) = page.front_matter.template() {
+        return template.to_string();
+    }
+    page.front_matter
+        .kind()
+        .map(|kind| format!("{}.html", kind))
+        .filter(|name| templates.get_template(name).is_ok())
+        .unwrap_or_else(|| "page.html".to_string())
+}
+
+/// Returns a Tera function that converts a URL path relative to the root of
+/// the project into an absolute URL.
+///
+/// For example
+///
+/// ```jinja2
+/// {{ absolute_url(path=this.path) | escape_xml | safe }}
+/// ```
+fn absolute_url(config: &Config) -> impl tera::Function {
+    let base_url = config.base_url();
+    move |args: &HashMap<String, json::Value>| -> tera::Result<json::Value> {
+        let path = match args.get("path") {
+            Some(json::Value::String(path)) => path,
+            Some(_) => return Err("`absolute_url` argument `path` must be a string".into()),
+            None => return Err("`absolute_url` requires a `path` argument".into()),
+        };
+        match &base_url {
+            Some(base_url) => Ok(json::Value::String(format!(
+                "{}{}",
+                base_url,
+                path.trim_start_matches('/')
+            ))),
+            None => Err("`absolute_url` requires `project.base_url` to be set".into()),
+        }
+    }
 }
 
 impl Theme {
+    /// Load every file in the given theme sub directory, layered over the
+    /// given defaults. Hidden files are ignored.
     fn load_theme_files_from_path<T>(
         theme_dir: &Path,
         sub_dir: &str,
@@ -134,25 +380,31 @@ impl Theme {
     where
         T: From<File>,
     {
-        defaults
+        let mut files: BTreeMap<PathBuf, Cow<'static, str>> = defaults
             .into_iter()
-            .map(|(name, default)| {
-                let relative_path: PathBuf = [sub_dir, name].iter().collect();
-                let path = theme_dir.join(&relative_path);
-                let contents = if path.exists() {
-                    Cow::from(fs::read_to_string(&path).context("failed to read file")?)
-                } else {
-                    Cow::from(default)
-                };
-                Ok((relative_path, contents).into())
-            })
-            .collect()
+            .map(|(name, default)| ([sub_dir, name].iter().collect(), Cow::from(default)))
+            .collect();
+        let dir = theme_dir.join(sub_dir);
+        for entry in walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path();
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("failed to read file `{}`", path.display()))?;
+            let relative_path = Path::new(sub_dir).join(path.strip_prefix(&dir).unwrap());
+            files.insert(relative_path, Cow::from(contents));
+        }
+        Ok(files.into_iter().map(T::from).collect())
     }
 
     /// Load a `Theme` from the given directory.
     ///
-    /// If corresponding templates are present in the directory then they will
-    /// override the default templates.
+    /// Every template and stylesheet in the directory is loaded, if any of
+    /// them have the same name as a default template or stylesheet then they
+    /// will override it.
     pub fn from_path(theme_dir: &Path) -> Result<Self> {
         // Load the templates from disk, or set defaults.
         let templates = Self::load_theme_files_from_path(
@@ -161,7 +413,16 @@ impl Theme {
             vec![
                 ("base.html", template::BASE),
                 ("index.html", template::INDEX),
+                ("macros.html", template::MACROS),
                 ("page.html", template::PAGE),
+                ("pagination.html", template::PAGINATION),
+                ("rss.xml", template::RSS),
+                ("atom.xml", template::ATOM),
+                ("sitemap.xml", template::SITEMAP),
+                ("robots.txt", template::ROBOTS),
+                ("section.html", template::SECTION),
+                ("taxonomy.html", template::TAXONOMY),
+                ("term.html", template::TERM),
             ],
         )?;
 
@@ -172,9 +433,22 @@ impl Theme {
             vec![("custom.css", stylesheet::CUSTOM)],
         )?;
 
+        // Load the static files from disk.
+        let static_dir = theme_dir.join("static");
+        let static_files = walkdir::WalkDir::new(&static_dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file())
+            .map(|e| {
+                let relative_path = e.path().strip_prefix(&static_dir).unwrap();
+                output::File::copy(relative_path.to_path_buf(), e.path().to_path_buf())
+            })
+            .collect();
+
         Ok(Self {
             templates,
             stylesheets,
+            static_files,
         })
     }
 
@@ -186,8 +460,20 @@ impl Theme {
             .collect()
     }
 
-    /// Render project pages using the given `Config`.
-    pub fn render(self, config: Config, pages: Vec<Page>) -> Result<output::Output> {
+    /// Render project pages using the given `Config`, only outputting the
+    /// pages that match the given predicate.
+    ///
+    /// All pages are still available to the templates, so listing pages like
+    /// `index.html` are always rendered.
+    pub fn render_filtered<F>(
+        self,
+        config: Config,
+        pages: Vec<Page>,
+        filter: F,
+    ) -> Result<output::Output>
+    where
+        F: Fn(&Page) -> bool,
+    {
         let mut output = output::Output::new(config);
 
         let mut templates = tera::Tera::default();
@@ -195,42 +481,159 @@ impl Theme {
             .add_raw_templates(self.raw_templates())
             .context("failed to register templates")?;
 
+        templates.register_function("absolute_url", absolute_url(output.config()));
+
         let mut base_ctx = tera::Context::new();
         base_ctx.insert("config", output.config().as_context());
+        base_ctx.insert("base_url", &output.config().base_url());
         base_ctx.insert("path_to_root", "");
 
         let mut page_ctx = base_ctx.clone();
         let mut pages_ctx = Vec::new();
+        let pretty_urls = output.config().pretty_urls();
+        let mut sections = Sections::new(pretty_urls);
+        let mut taxonomies: Vec<_> = output
+            .config()
+            .taxonomies()
+            .into_iter()
+            .map(|name| Taxonomy::new(name, pretty_urls))
+            .collect();
+
+        let mut urls = HashMap::new();
+        let mut output_paths = HashMap::new();
+        for page in &pages {
+            let output_path = page.output_path(output.config()).with_context(|| {
+                format!(
+                    "failed to get output path for page `{}`",
+                    page.path.display()
+                )
+            })?;
+            if let Some(other) = output_paths.insert(output_path.clone(), page.path.clone()) {
+                return Err(anyhow!(
+                    "pages `{}` and `{}` are both rendered to `{}`",
+                    other.display(),
+                    page.path.display(),
+                    output_path.display()
+                ));
+            }
+            urls.insert(page.path.clone(), page.url_path(output.config())?);
+        }
+        // Section index pages are rendered by their section's listing, so they
+        // do not replace it.
+        for page in pages.iter().filter(|page| page.is_section_index()) {
+            output_paths.remove(&page.output_path(output.config())?);
+        }
 
         for page in pages {
-            let this_ctx = page.context().with_context(|| {
+            let mut this_ctx = page.context(output.config(), &urls).with_context(|| {
                 format!(
                     "failed to generate render context for page `{}`",
                     page.path.display()
                 )
             })?;
+            if page.is_section_index() {
+                sections.insert(&page, &this_ctx);
+                continue;
+            }
+            let terms_ctx: json::Map<_, _> = output
+                .config()
+                .taxonomies()
+                .into_iter()
+                .zip(&taxonomies)
+                .map(|(name, taxonomy)| (name.to_string(), taxonomy.page_context(&page)))
+                .collect();
+            this_ctx["taxonomies"] = terms_ctx.into();
+            for taxonomy in &mut taxonomies {
+                taxonomy.insert(&page, &this_ctx);
+            }
+            sections.insert(&page, &this_ctx);
             page_ctx.insert("this", &this_ctx);
-            page_ctx.insert("path_to_root", &page.url_path_to_root()?);
+            page_ctx.insert("path_to_root", &page.url_path_to_root(output.config())?);
             pages_ctx.push(this_ctx);
-            let rendered = templates
-                .render("page.html", &page_ctx)
-                .with_context(|| format!("failed to render page `{}`", page.path.display()))?;
-            output.push_file(output::File::new(
-                page.path.with_extension("html"),
-                rendered,
-            ));
+            if !filter(&page) {
+                continue;
+            }
+            let template = page_template(&templates, &page);
+            let rendered = templates.render(&template, &page_ctx).with_context(|| {
+                format!(
+                    "failed to render page `{}` using template `{}`",
+                    page.path.display(),
+                    template
+                )
+            })?;
+            let output_path = page.output_path(output.config())?;
+            let source = page.source(output.config(), &urls)?;
+            output.push_file(output::File::new(output_path, rendered).with_source(source));
         }
 
+        // Everything rendered from here on is generated from all of the pages.
+        let generated = output.files().len();
+
+        let posts = posts(&pages_ctx);
         base_ctx.insert("pages", &json::Value::Array(pages_ctx));
-        let rendered = templates
-            .render("index.html", &base_ctx)
-            .context("failed to render page `index.html`")?;
-        output.push_file(output::File::new("index.html".into(), rendered));
+        base_ctx.insert("section", &sections.root_context());
+        let paginate_by = output.config().paginate_by();
+        // A page rendered to `index.html` replaces the index listing.
+        let pagers = if output_paths.contains_key(Path::new("index.html")) {
+            Vec::new()
+        } else {
+            paginate(&posts, paginate_by, "index.html", pretty_urls)
+        };
+        for pager in pagers {
+            let mut ctx = base_ctx.clone();
+            ctx.insert("path_to_root", &pager.path_to_root);
+            ctx.insert("paginator", &pager.context);
+            let rendered = templates
+                .render("index.html", &ctx)
+                .with_context(|| format!("failed to render page `{}`", pager.path.display()))?;
+            output.push_file(output::File::new(pager.path, rendered));
+        }
+
+        sections.render(&templates, &base_ctx, &output_paths, &mut output)?;
+
+        for taxonomy in &taxonomies {
+            taxonomy.render(&templates, &base_ctx, &mut output)?;
+        }
+
+        // Feeds, the sitemap, and robots.txt all require absolute URLs.
+        if let Some(base_url) = output.config().base_url() {
+            let posts: Vec<_> = posts
+                .into_iter()
+                .map(|mut post| {
+                    let content = renderer::absolute_links(
+                        post["content"].as_str().unwrap_or_default(),
+                        &base_url,
+                        post["path"].as_str().unwrap_or_default(),
+                    );
+                    post["content"] = content.into();
+                    post
+                })
+                .collect();
+            let mut ctx = base_ctx.clone();
+            ctx.insert("posts", &posts);
+            for name in &["rss.xml", "atom.xml", "sitemap.xml", "robots.txt"] {
+                let rendered = templates
+                    .render(name, &ctx)
+                    .with_context(|| format!("failed to render `{}`", name))?;
+                output.push_file(output::File::new(name.into(), rendered));
+            }
+        } else {
+            log::warn!("`project.base_url` is not set, skipping feed and sitemap generation");
+        }
 
         for stylesheet in self.stylesheets {
             output.push_file(stylesheet.into());
         }
 
+        for file in self.static_files {
+            output.push_file(file);
+        }
+
+        let highlight = renderer::highlight_stylesheet(output.config().highlight_theme())?;
+        output.push_file(output::File::new("css/highlight.css".into(), highlight));
+
+        check_output_paths(&output_paths, &output.files()[generated..])?;
+
         Ok(output)
     }
 }
@@ -243,13 +646,40 @@ impl Theme {
 mod tests {
     use super::*;
 
+    use crate::app::Project;
+    use crate::preprocess::Directives;
+
+    /// Render a project with the given config and source files, returning the
+    /// contents of each rendered text file.
+    fn render_project(config: &str, files: &[(&str, &str)]) -> Result<HashMap<PathBuf, String>> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        let src_dir = root_dir.join("src");
+        fs::create_dir(&src_dir).unwrap();
+        fs::write(root_dir.join("belong.toml"), config).unwrap();
+        for (path, contents) in files {
+            let path = src_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let output = Project::from_config(&Config::new(root_dir))?
+            .preprocess(&Directives::default())?
+            .render()?;
+        Ok(output
+            .files()
+            .iter()
+            .filter_map(|file| Some((file.path().to_path_buf(), file.text()?.to_string())))
+            .collect())
+    }
+
     #[test]
     fn page_url_path_multi_dir() {
         let page = Page {
             path: ["path", "segment", "index.html"].iter().collect(),
             ..Default::default()
         };
-        assert_eq!(page.url_path().unwrap(), "path/segment/index.html");
+        let config = Config::new(PathBuf::new());
+        assert_eq!(page.url_path(&config).unwrap(), "path/segment/index.html");
     }
 
     #[test]
@@ -258,7 +688,88 @@ mod tests {
             path: PathBuf::from("index.html"),
             ..Default::default()
         };
-        assert_eq!(page.url_path().unwrap(), "index.html");
+        let config = Config::new(PathBuf::new());
+        assert_eq!(page.url_path(&config).unwrap(), "index.html");
+    }
+
+    #[test]
+    fn page_url_path_pretty_urls() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::write(root_dir.join("belong.toml"), "[build]\npretty_urls = true").unwrap();
+        let config = Config::new(root_dir).load().unwrap();
+        let page = |path: &str| Page {
+            path: path.into(),
+            ..Default::default()
+        };
+        let url_path = |path| page(path).url_path(&config).unwrap();
+        let url_path_to_root = |path| page(path).url_path_to_root(&config).unwrap();
+        assert_eq!(url_path("hello.md"), "hello/");
+        assert_eq!(url_path_to_root("hello.md"), "../");
+        assert_eq!(url_path("posts/hello.md"), "posts/hello/");
+        assert_eq!(url_path_to_root("posts/hello.md"), "../../");
+        assert_eq!(url_path("posts/index.md"), "posts/");
+        assert_eq!(url_path_to_root("posts/index.md"), "../");
+        assert_eq!(url_path("posts/_index.md"), "posts/");
+        assert_eq!(url_path("index.md"), "");
+        assert_eq!(url_path_to_root("index.md"), "");
+    }
+
+    #[test]
+    fn page_output_path_permalinks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::write(
+            root_dir.join("belong.toml"),
+            r#"
+            [permalinks]
+            post = "/:year/:month/:slug/"
+            note = "/:section/:day-:slug"
+            draft = "/:unknown/"
+            "#,
+        )
+        .unwrap();
+        let config = Config::new(root_dir).load().unwrap();
+        let page = |path: &str, front_matter: &str| Page {
+            path: path.into(),
+            front_matter: toml::from_str(front_matter).unwrap(),
+            ..Default::default()
+        };
+        let output_path = |path, front_matter| page(path, front_matter).output_path(&config);
+        assert_eq!(
+            output_path("posts/hello.md", "kind = \"post\"\ndate = \"2020-03-21\"").unwrap(),
+            ["2020", "03", "hello", "index.html"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+        assert_eq!(
+            output_path(
+                "posts/hello.md",
+                "kind = \"post\"\ndate = \"2020-03-21\"\nslug = \"hi\""
+            )
+            .unwrap(),
+            ["2020", "03", "hi", "index.html"]
+                .iter()
+                .collect::<PathBuf>()
+        );
+        assert_eq!(
+            output_path("notes/a/b.md", "kind = \"note\"\ndate = \"2020-03-01\"").unwrap(),
+            ["notes", "a", "01-b.html"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(
+            output_path("about.md", "slug = \"about-me\"").unwrap(),
+            PathBuf::from("about-me.html")
+        );
+        assert!(output_path("posts/hello.md", "kind = \"post\"").is_err());
+        assert!(output_path("posts/hello.md", "kind = \"draft\"").is_err());
+        assert!(output_path("about.md", "slug = \"../escape\"").is_err());
+        assert!(output_path("about.md", "slug = \"/escape\"").is_err());
+        assert!(output_path("about.md", "slug = \"\"").is_err());
+        assert!(output_path(
+            "posts/hello.md",
+            "kind = \"post\"\ndate = \"2020-03-21\"\nslug = \"../../..\""
+        )
+        .is_err());
     }
 
     #[test]
@@ -267,7 +778,8 @@ mod tests {
             path: "index.html".into(),
             ..Default::default()
         };
-        assert_eq!(page.url_path_to_root().unwrap(), "");
+        let config = Config::new(PathBuf::new());
+        assert_eq!(page.url_path_to_root(&config).unwrap(), "");
     }
 
     #[test]
@@ -276,7 +788,8 @@ mod tests {
             path: ["path", "segment", "index.html"].iter().collect(),
             ..Default::default()
         };
-        assert_eq!(page.url_path_to_root().unwrap(), "../../");
+        let config = Config::new(PathBuf::new());
+        assert_eq!(page.url_path_to_root(&config).unwrap(), "../../");
     }
 
     #[test]
@@ -286,6 +799,281 @@ mod tests {
             path: ["/", "path", "segment"].iter().collect(),
             ..Default::default()
         };
-        page.url_path_to_root().unwrap();
+        let config = Config::new(PathBuf::new());
+        page.url_path_to_root(&config).unwrap();
+    }
+
+    #[test]
+    fn absolute_url_function() {
+        use tera::Function;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root_dir = temp_dir.path().to_path_buf();
+        fs::write(root_dir.join("belong.toml"), "").unwrap();
+        let f = absolute_url(&Config::new(root_dir.clone()).load().unwrap());
+        let mut args = HashMap::new();
+        args.insert("path".to_string(), json!("posts/hello.html"));
+        assert_eq!(
+            f.call(&args).unwrap_err().to_string(),
+            "`absolute_url` requires `project.base_url` to be set"
+        );
+
+        fs::write(
+            root_dir.join("belong.toml"),
+            "[project]\nbase_url = \"https://example.com\"",
+        )
+        .unwrap();
+        let f = absolute_url(&Config::new(root_dir).load().unwrap());
+        assert_eq!(
+            f.call(&args).unwrap(),
+            json!("https://example.com/posts/hello.html")
+        );
+    }
+
+    #[test]
+    fn render_absolute_urls_unescaped() {
+        let files = render_project(
+            "[project]\nbase_url = \"https://example.com\"",
+            &[
+                ("a.md", "+++\ntitle = \"A\"\n+++\n"),
+                (
+                    "q&a.md",
+                    "+++\ntitle = \"Q&A\"\nkind = \"post\"\ndate = \"2020-01-01\"\n+++\n",
+                ),
+            ],
+        )
+        .unwrap();
+        assert!(files[Path::new("a.html")]
+            .contains(r#"<link rel="canonical" href="https://example.com/a.html">"#));
+        assert!(files[Path::new("rss.xml")].contains("<link>https://example.com/</link>"));
+        assert!(files[Path::new("sitemap.xml")].contains("<loc>https://example.com/a.html</loc>"));
+
+        // Characters that are special in XML are still escaped.
+        assert!(files[Path::new("q&a.html")]
+            .contains(r#"<link rel="canonical" href="https://example.com/q&amp;a.html">"#));
+        assert!(
+            files[Path::new("sitemap.xml")].contains("<loc>https://example.com/q&amp;a.html</loc>")
+        );
+        assert!(
+            files[Path::new("rss.xml")].contains("<link>https://example.com/q&amp;a.html</link>")
+        );
+        assert!(files[Path::new("atom.xml")]
+            .contains(r#"<link href="https://example.com/q&amp;a.html"/>"#));
+    }
+
+    #[test]
+    fn render_feeds() {
+        let post = |date, contents| {
+            format!(
+                "+++\ntitle = \"Post\"\nkind = \"post\"\ndate = \"{}\"\n+++\n{}",
+                date, contents
+            )
+        };
+        let files = render_project(
+            "[project]\nbase_url = \"https://example.com\"\n[build]\npretty_urls = true",
+            &[
+                ("posts/a.md", &post("2020-01-01", "[b](b.md) ![i](i.png)")),
+                ("posts/b.md", &post("2020-02-01", "[home](../../)")),
+                ("about.md", "+++\ntitle = \"About\"\n+++\n"),
+            ],
+        )
+        .unwrap();
+        for name in &["rss.xml", "atom.xml"] {
+            let feed = &files[Path::new(name)];
+            let a = feed
+                .find(&tera::escape_html(
+                    r#"<a href="https://example.com/posts/b/">b</a> <img src="https://example.com/posts/i.png""#,
+                ))
+                .unwrap();
+            let b = feed
+                .find(&tera::escape_html(
+                    r#"<a href="https://example.com/">home</a>"#,
+                ))
+                .unwrap();
+            assert!(b < a, "posts are not newest first in `{}`", name);
+            assert!(!feed.contains("https://example.com/about/"));
+        }
+    }
+
+    #[test]
+    fn render_sitemap_and_robots() {
+        let files = render_project(
+            "[project]\nbase_url = \"https://example.com/blog\"",
+            &[
+                ("a.md", "+++\ndate = \"2020-01-01\"\n+++\n"),
+                (
+                    "b.md",
+                    "+++\ndate = \"2020-01-01\"\nupdated = \"2020-02-01\"\n+++\n",
+                ),
+                ("c.md", "+++\nsitemap = false\n+++\n"),
+                ("d.md", ""),
+            ],
+        )
+        .unwrap();
+        let sitemap = &files[Path::new("sitemap.xml")];
+        let url = |loc| {
+            let start = sitemap.find(&format!("<loc>{}</loc>", loc))?;
+            let end = start + sitemap[start..].find("</url>").unwrap();
+            Some(sitemap[start..end].to_string())
+        };
+        assert!(url("https://example.com/blog/index.html").is_some());
+        assert!(url("https://example.com/blog/a.html")
+            .unwrap()
+            .contains("<lastmod>2020-01-01</lastmod>"));
+        assert!(url("https://example.com/blog/b.html")
+            .unwrap()
+            .contains("<lastmod>2020-02-01</lastmod>"));
+        assert_eq!(url("https://example.com/blog/c.html"), None);
+        assert!(!url("https://example.com/blog/d.html")
+            .unwrap()
+            .contains("<lastmod>"));
+
+        assert_eq!(
+            files[Path::new("robots.txt")].trim_end(),
+            "User-agent: *\nAllow: /\nSitemap: https://example.com/blog/sitemap.xml"
+        );
+    }
+
+    #[test]
+    fn render_without_base_url() {
+        let files = render_project("", &[("a.md", "")]).unwrap();
+        for name in &["rss.xml", "atom.xml", "sitemap.xml", "robots.txt"] {
+            assert!(!files.contains_key(Path::new(name)));
+        }
+    }
+
+    #[test]
+    fn render_pages_replace_listings() {
+        let files = render_project(
+            "[build]\npretty_urls = true\npaginate_by = 1",
+            &[
+                ("index.md", "Home"),
+                ("docs/index.md", "Docs"),
+                ("docs/a.md", ""),
+                ("posts.md", "Posts"),
+                ("posts/a.md", ""),
+                ("posts/b.md", ""),
+                ("notes/_index.md", "Notes"),
+                ("notes/a.md", ""),
+                ("notes/b.md", ""),
+            ],
+        )
+        .unwrap();
+        let path = |path: &str| path.split('/').collect::<PathBuf>();
+        assert!(files[&path("index.html")].contains("<p>Home</p>"));
+        assert!(files[&path("docs/index.html")].contains("<p>Docs</p>"));
+        assert!(files[&path("posts/index.html")].contains("<p>Posts</p>"));
+        assert!(!files.contains_key(&path("posts/page/2/index.html")));
+        assert!(files[&path("notes/index.html")].contains("<p>Notes</p>"));
+        assert!(files.contains_key(&path("notes/page/2/index.html")));
+    }
+
+    #[test]
+    fn render_generated_path_conflicts() {
+        let err = render_project(
+            "[build]\npretty_urls = true\npaginate_by = 1",
+            &[
+                (
+                    "a.md",
+                    "+++\ntitle = \"A\"\nkind = \"post\"\ndate = \"2020-01-01\"\n+++\n",
+                ),
+                (
+                    "b.md",
+                    "+++\ntitle = \"B\"\nkind = \"post\"\ndate = \"2020-01-02\"\n+++\n",
+                ),
+                ("page/2.md", ""),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            format!(
+                "failed to render project: page `{}` and a generated file are both rendered \
+                 to `{}`",
+                ["page", "2.md"].iter().collect::<PathBuf>().display(),
+                Path::new("page/2/index.html").display()
+            )
+        );
+
+        let err = render_project(
+            "",
+            &[("a.md", "+++\ntags = [\"rust\"]\n+++\n"), ("tags/b.md", "")],
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            format!(
+                "failed to render project: `{}` is generated more than once",
+                ["tags", "index.html"].iter().collect::<PathBuf>().display()
+            )
+        );
+    }
+
+    #[test]
+    fn render_invalid_slug() {
+        let err = render_project("", &[("a.md", "+++\nslug = \"../escape\"\n+++\n")]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "failed to render project: failed to get output path for page `a.md`: slug \
+             `../escape` must be a relative path without `.` or `..` components"
+        );
+    }
+
+    #[test]
+    fn theme_from_path_layers_over_defaults() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let theme_dir = temp_dir.path();
+        let partials_dir = theme_dir.join("templates").join("partials");
+        fs::create_dir_all(&partials_dir).unwrap();
+        fs::write(partials_dir.join("header.html"), "<header>").unwrap();
+        fs::write(theme_dir.join("templates").join("page.html"), "custom").unwrap();
+        fs::write(theme_dir.join("templates").join(".page.html.swp"), "").unwrap();
+        fs::create_dir_all(theme_dir.join("css")).unwrap();
+        fs::write(theme_dir.join("css").join("extra.css"), "body {}").unwrap();
+
+        let theme = Theme::from_path(theme_dir).unwrap();
+        let templates: HashMap<_, _> = theme.raw_templates().into_iter().collect();
+        assert_eq!(templates["partials/header.html"], "<header>");
+        assert_eq!(templates["page.html"], "custom");
+        assert_eq!(templates["base.html"], template::BASE);
+        assert!(!templates.contains_key(".page.html.swp"));
+        let stylesheets: Vec<_> = theme.stylesheets.iter().map(|s| s.path.clone()).collect();
+        assert_eq!(
+            stylesheets,
+            vec![
+                ["css", "custom.css"].iter().collect::<PathBuf>(),
+                ["css", "extra.css"].iter().collect::<PathBuf>(),
+            ]
+        );
+    }
+
+    #[test]
+    fn page_template_fallback() {
+        let mut templates = tera::Tera::default();
+        templates
+            .add_raw_templates(vec![("page.html", ""), ("post.html", "")])
+            .unwrap();
+        let page = |contents: &str| {
+            let temp_dir = tempfile::tempdir().unwrap();
+            let path = temp_dir.path().join("page.md");
+            fs::write(&path, contents).unwrap();
+            Page::from_path(temp_dir.path(), &path).unwrap()
+        };
+        assert_eq!(page_template(&templates, &page("")), "page.html");
+        assert_eq!(
+            page_template(&templates, &page("+++\nkind = \"post\"\n+++\n")),
+            "post.html"
+        );
+        assert_eq!(
+            page_template(&templates, &page("+++\nkind = \"project\"\n+++\n")),
+            "page.html"
+        );
+        assert_eq!(
+            page_template(
+                &templates,
+                &page("+++\nkind = \"post\"\ntemplate = \"landing.html\"\n+++\n")
+            ),
+            "landing.html"
+        );
     }
 }
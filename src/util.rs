@@ -4,7 +4,7 @@ use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
 
 use crate::prelude::*;
@@ -88,18 +88,126 @@ where
     }
 }
 
+/// Lexically normalize a path by removing any `.` and `..` components.
+///
+/// Unlike [`fs::canonicalize`] this does not touch the file system, so the
+/// path does not need to exist.
+///
+/// [`fs::canonicalize`]: https://doc.rust-lang.org/std/fs/fn.canonicalize.html
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => result.push(".."),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Canonicalize a path, even if the file itself no longer exists.
+pub fn canonicalize(path: PathBuf) -> PathBuf {
+    if let Ok(path) = fs::canonicalize(&path) {
+        return path;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => match fs::canonicalize(parent) {
+            Ok(parent) => parent.join(name),
+            Err(_) => path,
+        },
+        _ => path,
+    }
+}
+
+/// Whether the path only has normal components, meaning it is relative and
+/// can not escape the directory it is joined onto.
+pub fn is_normal_path(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Convert the location of an output file, relative to the output directory,
+/// into the URL path used to link to it.
+///
+/// With pretty URLs the `index.html` is dropped, for example
+/// `posts/hello-world/index.html` becomes `posts/hello-world/`.
+pub fn url_path(output_path: &str, pretty_urls: bool) -> String {
+    if pretty_urls && (output_path == "index.html" || output_path.ends_with("/index.html")) {
+        output_path.trim_end_matches("index.html").to_string()
+    } else {
+        output_path.to_string()
+    }
+}
+
+/// Decode the percent-encoded characters in a URL or URL path segment.
+///
+/// Returns `None` if an escape is malformed or the result is not UTF-8.
+pub fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Convert a string into a URL friendly slug.
+///
+/// For example `Hello World!` becomes `hello-world`.
+pub fn slugify(s: &str) -> String {
+    let mut slug = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    slug
+}
+
 /// Completely delete and recreate a directory.
 pub fn recreate_dir<P>(dir: P) -> Result<()>
 where
     P: AsRef<Path>,
 {
     let dir = dir.as_ref();
-    if let Err(e) = fs::remove_dir_all(&dir) {
+    if let Err(e) = fs::remove_dir_all(dir) {
         if e.kind() != io::ErrorKind::NotFound {
             return Err(e).context("failed to remove directory");
         }
     }
-    fs::create_dir_all(&dir).context("failed to create directory")?;
+    fs::create_dir_all(dir).context("failed to create directory")?;
+    Ok(())
+}
+
+/// Remove a file, ignoring the error if it does not exist.
+pub fn remove_file<P>(path: P) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if let Err(e) = fs::remove_file(path) {
+        if e.kind() != io::ErrorKind::NotFound {
+            return Err(e).with_context(|| format!("failed to remove file `{}`", path.display()));
+        }
+    }
     Ok(())
 }
 
@@ -120,3 +228,59 @@ where
         .with_context(|| format!("failed to write to file `{}`", path.display()))?;
     Ok(())
 }
+
+/////////////////////////////////////////////////////////////////////////
+// Unit tests
+/////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_decode_basic() {
+        assert_eq!(percent_decode("hello").unwrap(), "hello");
+        assert_eq!(percent_decode("hello%20world").unwrap(), "hello world");
+        assert_eq!(percent_decode("%e2%9c%a8").unwrap(), "✨");
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+        assert_eq!(percent_decode("%ff"), None);
+    }
+
+    #[test]
+    fn slugify_basic() {
+        assert_eq!(slugify("Hello World!"), "hello-world");
+        assert_eq!(slugify("  Rust & WebAssembly  "), "rust-webassembly");
+        assert_eq!(slugify("snake_case"), "snake_case");
+        assert_eq!(slugify("Ünïcödé"), "ünïcödé");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn url_path_pretty() {
+        assert_eq!(url_path("index.html", false), "index.html");
+        assert_eq!(url_path("index.html", true), "");
+        assert_eq!(url_path("posts/a/index.html", true), "posts/a/");
+        assert_eq!(url_path("posts/a.html", true), "posts/a.html");
+        assert_eq!(url_path("posts/myindex.html", true), "posts/myindex.html");
+    }
+
+    #[test]
+    fn normalize_path_basic() {
+        let path: PathBuf = ["a", ".", "b", "..", "c"].iter().collect();
+        assert_eq!(
+            normalize_path(&path),
+            ["a", "c"].iter().collect::<PathBuf>()
+        );
+        let path: PathBuf = ["..", "a", "..", ".."].iter().collect();
+        assert_eq!(
+            normalize_path(&path),
+            ["..", ".."].iter().collect::<PathBuf>()
+        );
+        let path: PathBuf = ["/", "..", "a"].iter().collect();
+        assert_eq!(
+            normalize_path(&path),
+            ["/", "a"].iter().collect::<PathBuf>()
+        );
+    }
+}
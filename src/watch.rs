@@ -0,0 +1,161 @@
+//! Watch a `Project` for changes on disk.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+use crate::app::Incremental;
+use crate::config::Config;
+use crate::output::Output;
+use crate::prelude::*;
+use crate::util;
+
+/// How long to wait for file system events to settle before reporting them.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/////////////////////////////////////////////////////////////////////////
+// Watcher definitions
+/////////////////////////////////////////////////////////////////////////
+
+/// Watches a project's source directory, theme directory, config file, and
+/// any extra files that pages depend on.
+pub struct Watcher {
+    /// The underlying file system watcher.
+    inner: RecommendedWatcher,
+    /// Receives file system events from the watcher.
+    rx: mpsc::Receiver<DebouncedEvent>,
+    /// The config file.
+    config_path: PathBuf,
+    /// The output directory, changes in here are always ignored.
+    output_dir: PathBuf,
+    /// Directories whose entire contents are watched.
+    dirs: Vec<PathBuf>,
+    /// Individual files that are watched.
+    files: HashSet<PathBuf>,
+    /// Directories that are watched non-recursively.
+    parents: HashSet<PathBuf>,
+}
+
+/////////////////////////////////////////////////////////////////////////
+// Watcher implementations
+/////////////////////////////////////////////////////////////////////////
+
+/// Returns the paths affected by a file system event.
+fn event_paths(event: DebouncedEvent) -> Vec<PathBuf> {
+    match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::Error(err, path) => {
+            log::warn!("file watcher error: {}", err);
+            path.into_iter().collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl Watcher {
+    /// Start watching the project with the given `Config`.
+    pub fn new(config: &Config) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let inner = notify::watcher(tx, DEBOUNCE).context("failed to create file watcher")?;
+        let mut watcher = Self {
+            inner,
+            rx,
+            config_path: util::canonicalize(config.path().to_path_buf()),
+            output_dir: util::canonicalize(config.output_dir()),
+            dirs: Vec::new(),
+            files: HashSet::new(),
+            parents: HashSet::new(),
+        };
+        watcher.watch_file(config.path())?;
+        for dir in &[config.src_dir(), config.theme_dir()] {
+            if dir.exists() {
+                watcher
+                    .inner
+                    .watch(dir, RecursiveMode::Recursive)
+                    .with_context(|| format!("failed to watch `{}`", dir.display()))?;
+                watcher.dirs.push(util::canonicalize(dir.clone()));
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Whether the given path is being watched.
+    fn is_watched(&self, path: &Path) -> bool {
+        !path.starts_with(&self.output_dir)
+            && (path == self.config_path
+                || self.files.contains(path)
+                || self.dirs.iter().any(|dir| path.starts_with(dir)))
+    }
+
+    /// Additionally watch the given file.
+    ///
+    /// The file's parent directory is watched rather than the file itself so
+    /// that we pick up changes even if an editor replaces the file instead of
+    /// writing to it.
+    pub fn watch_file(&mut self, path: &Path) -> Result<()> {
+        let path = util::canonicalize(path.to_path_buf());
+        if self.is_watched(&path) {
+            return Ok(());
+        }
+        let parent = path.parent().unwrap().to_path_buf();
+        if !self.parents.contains(&parent) {
+            self.inner
+                .watch(&parent, RecursiveMode::NonRecursive)
+                .with_context(|| format!("failed to watch `{}`", parent.display()))?;
+            self.parents.insert(parent);
+        }
+        self.files.insert(path);
+        Ok(())
+    }
+
+    /// Block until one or more watched files change and return their paths.
+    pub fn recv(&self) -> Result<Vec<PathBuf>> {
+        loop {
+            let event = self.rx.recv().context("file watcher disconnected")?;
+            let mut paths = event_paths(event);
+            while let Ok(event) = self.rx.try_recv() {
+                paths.extend(event_paths(event));
+            }
+            let mut paths: Vec<_> = paths
+                .into_iter()
+                .map(util::canonicalize)
+                .filter(|path| self.is_watched(path))
+                .collect();
+            paths.sort();
+            paths.dedup();
+            if !paths.is_empty() {
+                return Ok(paths);
+            }
+        }
+    }
+}
+
+/// Incrementally rebuild the project whenever it changes.
+///
+/// The given closure is called with the `Output` after every successful
+/// rebuild. This function only returns if the watcher fails.
+pub fn rebuild_on_change<F>(build: &mut Incremental, mut f: F) -> Result<()>
+where
+    F: FnMut(&Output),
+{
+    let mut watcher = Watcher::new(build.config())?;
+    loop {
+        for path in build.includes() {
+            watcher.watch_file(path)?;
+        }
+        let paths = watcher.recv()?;
+        for path in &paths {
+            log::info!("detected change in `{}`", path.display());
+        }
+        match build.rebuild(&paths) {
+            Ok(output) => f(&output),
+            Err(err) => log::error!("{:?}", err),
+        }
+    }
+}